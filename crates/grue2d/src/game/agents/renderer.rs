@@ -6,7 +6,12 @@ mod screen;
 use screen::*;
 
 use {
-    rlbox::{render::tiled as tiled_render, rl::grid2d::Vec2i, utils::DoubleTrack},
+    rlbox::{
+        render::tiled as tiled_render,
+        rl::grid2d::Vec2i,
+        utils::DoubleTrack,
+        view::{actor::ActorImage, map::RlMapView},
+    },
     snow2d::{
         gfx::{draw::*, Color, GameClock, Snow2d, WindowState},
         ui::Ui,
@@ -23,6 +28,9 @@ use crate::game::{
 /// TODO: remove
 const WALK_TIME: f32 = 8.0 / 60.0;
 
+/// Alpha of actors remembered but not visible
+const REMEMBERED_ALPHA: f32 = 96.0;
+
 /// Sort actors based on position
 #[derive(Debug, PartialEq, Eq)]
 struct ActorSortEntry {
@@ -71,18 +79,43 @@ impl WorldRenderer {
         }
     }
 
+    /// Renders map layers. Doors out of sight are drawn as they were last seen
     pub fn render_map(
         screen: &mut impl DrawApi,
         world: &World,
         layer_range: impl std::ops::RangeBounds<i32>,
     ) {
-        tiled_render::render_tiled(
-            screen,
-            &world.map.tiled,
-            &world.map.idmap,
-            world.cam.bounds(),
-            layer_range,
-        );
+        match &world.map {
+            RlMapView::Tiled(map) => {
+                tiled_render::render_tiled_with(
+                    screen,
+                    &map.tiled,
+                    &map.idmap,
+                    world.cam.bounds(),
+                    layer_range,
+                    |pos, gid| {
+                        if world.shadow.is_visible(pos) {
+                            return gid;
+                        }
+                        match world.memory.cell(pos).and_then(|c| c.door) {
+                            Some(mem) => map.door_gid(pos, gid, mem),
+                            None => gid,
+                        }
+                    },
+                );
+            }
+            RlMapView::Grid(map) => {
+                // autotiled layers don't change with door states, so there's nothing to remember
+                tiled_render::render_gid_layers(
+                    screen,
+                    map,
+                    &map.layers,
+                    &map.idmap,
+                    world.cam.bounds(),
+                    layer_range,
+                );
+            }
+        }
     }
 
     fn update_actor_images(&mut self, world: &World, dt: Duration) {
@@ -97,7 +130,7 @@ impl WorldRenderer {
 
             // update interpolation value
             {
-                let is_visible = world.shadow.fov.a.is_in_view(actor.pos);
+                let is_visible = world.shadow.is_visible(actor.pos);
 
                 // on visibility change
                 if is_visible != v.a {
@@ -121,14 +154,17 @@ impl WorldRenderer {
         }
     }
 
-    fn actor_alpha_f32(&self, slot: usize) -> f32 {
-        fn b2f(b: bool) -> f32 {
+    /// `is_remembered`: if the actor is remembered (drawn dimmed when not visible)
+    fn actor_alpha_f32(&self, slot: usize, is_remembered: bool) -> f32 {
+        let b2f = |b: bool| {
             if b {
                 255.0
+            } else if is_remembered {
+                REMEMBERED_ALPHA
             } else {
                 0.0
             }
-        }
+        };
 
         let v = &self.actor_visibilities[slot];
         b2f(v.a) * v.t + b2f(v.b) * (1.0 - v.t)
@@ -142,11 +178,19 @@ impl WorldRenderer {
         for (entry_ix, entry) in self.sort_buf.iter().enumerate() {
             let actor = &world.entities[entry.actor_index];
 
-            let alpha = self.actor_alpha_f32(entry.actor_index.slot() as usize) as u8;
+            let slot = entry.actor_index.slot();
+            let v = &self.actor_visibilities[slot as usize];
+            // not visible, but remembered
+            let memory = world.memory.entity(&slot).filter(|_| !v.a);
+            let alpha = self.actor_alpha_f32(slot as usize, memory.is_some()) as u8;
 
             let base_node = &mut ui.nodes[&actor.nodes.base];
             base_node.z_order = entry_ix as f32 / n_entries;
-            base_node.params.pos = actor.view.base_pos_world(&world.map.tiled);
+            base_node.params.pos = match memory {
+                // draw at the last seen position
+                Some(mem) => ActorImage::align_base(mem.pos, &world.map),
+                None => actor.view.base_pos_world(&world.map),
+            };
 
             let img_node = &mut ui.nodes[&actor.nodes.img];
             img_node.z_order = entry_ix as f32 / n_entries;
@@ -164,15 +164,11 @@ impl ShadowRenderer {
         }
 
         // get shadow texture
-        tiled_render::render_fov_fow_blend(
+        tiled_render::render_shadow_blend(
             &mut offscreen,
-            &world.map.tiled,
+            &world.map,
             &world.cam.bounds(),
-            &world.shadow.fov.a,
-            &world.shadow.fov.b,
-            world.shadow.dt.get(),
-            &world.shadow.fow.a,
-            &world.shadow.fow.b,
+            &world.shadow,
         );
 
         drop(offscreen);
@@ -2,6 +2,7 @@
 Turn-based game state
 */
 
+pub mod ai;
 pub mod anim;
 pub mod ev;
 pub mod script;
@@ -14,6 +15,8 @@ use self::{anim::AnimPlayer, script::ScriptRef};
 pub struct Rogue {
     pub anims: AnimPlayer,
     pub script_to_play: Option<ScriptRef>,
+    /// Number of turns the player took in this run
+    pub turns: u32,
 }
 
 impl Rogue {
@@ -21,6 +24,7 @@ impl Rogue {
         Self {
             anims: AnimPlayer::default(),
             script_to_play: None,
+            turns: 0,
         }
     }
 }
@@ -0,0 +1,487 @@
+/*!
+AI of non-player actors
+
+[`Brain`] decides the [`Action`] of an actor's turn from what the actor sees. Brains are selected
+per [`ActorType`] in RON files (see [`AiType`]).
+*/
+
+use std::{collections::HashMap, fmt};
+
+use snow2d::utils::arena::Index;
+
+use rlbox::rl::{
+    dijkstra::DijkstraMap,
+    grid2d::*,
+    path::{self, Occupied, PathParams},
+    rlmap::RlMap,
+    shadow::{self, FovAlgorithm, FovData, FovRefreshParams, LightMap},
+};
+
+use crate::game::{
+    ctrl::rogue::{ev, tick::Event},
+    data::world::{
+        actor::{Actor, ActorType, AiType, Relation},
+        World,
+    },
+};
+
+/// Coefficient of flee maps (see [`DijkstraMap::flee`])
+const FLEE_COEFF: f32 = 1.2;
+
+/// Decides actions of an actor's turn
+pub trait Brain: fmt::Debug {
+    fn decide(&self, cx: &mut AiContext) -> Action;
+}
+
+/// Creates the event of the actor's turn with the brain selected by the actor type
+pub fn decide(world: &mut World, actor: Index<Actor>) -> Box<dyn Event> {
+    let me = &world.entities[actor];
+
+    let (ai, max_hp) = match ActorType::from_type_key(&me.type_id) {
+        Ok(type_) => (type_.ai.clone(), type_.stats.hp),
+        Err(err) => {
+            log::warn!("failed to get the actor type of {:?}: {}", actor, err);
+            (AiType::default(), me.stats.hp)
+        }
+    };
+
+    let others = world
+        .entities
+        .iter()
+        .filter(|(ix, _e)| *ix != actor)
+        .map(|(_ix, e)| Body::from(e))
+        .collect();
+
+    let mut cx = AiContext::new(
+        world.map.rlmap(),
+        Some(&world.shadow.light.a),
+        world.shadow.algo,
+        (Body::from(me), max_hp),
+        others,
+        &mut world.ai,
+    );
+
+    self::brain(&ai).decide(&mut cx).into_event(actor)
+}
+
+pub fn brain(ai: &AiType) -> Box<dyn Brain> {
+    match ai {
+        AiType::Wander => Box::new(Wander),
+        AiType::Chase => Box::new(Chase),
+        AiType::KeepDistance { dist } => Box::new(KeepDistance { dist: *dist }),
+        AiType::Guard { radius } => Box::new(Guard { radius: *radius }),
+        AiType::FleeAtLowHp { ratio } => Box::new(FleeAtLowHp { ratio: *ratio }),
+    }
+}
+
+/// Output of [`Brain`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Walks to a random direction
+    Wander,
+    Walk(Dir8),
+    MeleeAttack(Dir8),
+    Rest,
+}
+
+impl Action {
+    pub fn into_event(self, actor: Index<Actor>) -> Box<dyn Event> {
+        match self {
+            Self::Wander => Box::new(ev::RandomWalk { actor }),
+            Self::Walk(dir) => Box::new(ev::Walk { actor, dir }),
+            Self::MeleeAttack(dir) => Box::new(ev::MeleeAttack {
+                actor,
+                dir: Some(dir),
+            }),
+            Self::Rest => Box::new(ev::RestOneTurn { actor }),
+        }
+    }
+}
+
+/// What AI knows about an actor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Body {
+    pub pos: Vec2i,
+    pub home: Vec2i,
+    pub hp: u32,
+    /// FoV radius
+    pub view: u32,
+    pub relation: Relation,
+}
+
+impl From<&Actor> for Body {
+    fn from(actor: &Actor) -> Self {
+        Self {
+            pos: actor.pos,
+            home: actor.home,
+            hp: actor.stats.hp,
+            view: actor.stats.view,
+            relation: actor.relation,
+        }
+    }
+}
+
+/// Buffers reused over AI turns
+#[derive(Debug, Default)]
+pub struct AiCache {
+    /// FoV of the actor taking turn
+    fov: FovData,
+    /// Flee maps keyed by their sources
+    flee_maps: HashMap<Vec2i, DijkstraMap>,
+}
+
+impl AiCache {
+    /// Drops cached maps. Call it when the map or the targets change (e.g. on player's turn)
+    pub fn clear(&mut self) {
+        self.flee_maps.clear();
+    }
+}
+
+/// What an actor knows on its turn
+#[derive(Debug)]
+pub struct AiContext<'a> {
+    pub rlmap: &'a RlMap,
+    /// `None` if every cell is lit
+    pub light: Option<&'a LightMap>,
+    pub me: Body,
+    pub max_hp: u32,
+    /// Actors other than `me`
+    pub others: Vec<Body>,
+    cache: &'a mut AiCache,
+}
+
+impl<'a> AiContext<'a> {
+    /// Refreshes the FoV of the actor in the cache
+    pub fn new(
+        rlmap: &'a RlMap,
+        light: Option<&'a LightMap>,
+        algo: FovAlgorithm,
+        (me, max_hp): (Body, u32),
+        others: Vec<Body>,
+        cache: &'a mut AiCache,
+    ) -> Self {
+        shadow::refresh_fov(
+            &mut cache.fov,
+            FovRefreshParams {
+                r: me.view,
+                origin: me.pos,
+                opa: rlmap,
+                algo,
+            },
+        );
+
+        Self {
+            rlmap,
+            light,
+            me,
+            max_hp,
+            others,
+            cache,
+        }
+    }
+
+    /// If the cell is in the actor's FoV and lit
+    pub fn can_see(&self, pos: Vec2i) -> bool {
+        self.cache.fov.is_in_view(pos) && self.light.map_or(true, |light| light.is_lit(pos))
+    }
+
+    /// The nearest visible actor with the other relation
+    pub fn target(&self) -> Option<Body> {
+        self.others
+            .iter()
+            .filter(|e| e.relation != self.me.relation)
+            .filter(|e| self.can_see(e.pos))
+            .min_by_key(|e| (e.pos - self.me.pos).len_king())
+            .copied()
+    }
+
+    fn is_occupied(&self, pos: Vec2i) -> bool {
+        self.me.pos == pos || self.others.iter().any(|e| e.pos == pos)
+    }
+
+    /// First step of the path to the position and the length of the path
+    fn step_to(&self, pos: Vec2i) -> Option<(Dir8, usize)> {
+        let map = Occupied::new(self.rlmap, |p| self.is_occupied(p));
+        let path = path::find_path(&map, self.me.pos, pos, &PathParams::default())?;
+        path.first().map(|dir| (*dir, path.len()))
+    }
+
+    /// Attacks the target if it's next to the actor, or walks a step toward it
+    pub fn chase(&self, target: &Body) -> Action {
+        match self.step_to(target.pos) {
+            Some((dir, 1)) => Action::MeleeAttack(dir),
+            Some((dir, _len)) => Action::Walk(dir),
+            None => Action::Rest,
+        }
+    }
+
+    /// Walks a step toward the position
+    pub fn approach(&self, pos: Vec2i) -> Action {
+        match self.step_to(pos) {
+            Some((dir, _len)) => Action::Walk(dir),
+            None => Action::Rest,
+        }
+    }
+
+    /// Walks a step away from the position, avoiding dead ends
+    ///
+    /// Flee maps are cached until [`AiCache::clear`].
+    pub fn flee_from(&mut self, pos: Vec2i) -> Action {
+        let rlmap = self.rlmap;
+        let dmap = self.cache.flee_maps.entry(pos).or_insert_with(|| {
+            DijkstraMap::new(rlmap, rlmap.size(), Some(pos), &PathParams::default())
+                .flee(rlmap, FLEE_COEFF)
+        });
+
+        match dmap.downhill_dir(self.me.pos) {
+            Some(dir) => Action::Walk(dir),
+            None => Action::Rest,
+        }
+    }
+}
+
+/// Walks randomly
+#[derive(Debug, Clone)]
+pub struct Wander;
+
+impl Brain for Wander {
+    fn decide(&self, _cx: &mut AiContext) -> Action {
+        Action::Wander
+    }
+}
+
+/// Approaches and attacks the target. Wanders if there's no target
+#[derive(Debug, Clone)]
+pub struct Chase;
+
+impl Brain for Chase {
+    fn decide(&self, cx: &mut AiContext) -> Action {
+        match cx.target() {
+            Some(target) => cx.chase(&target),
+            None => Wander.decide(cx),
+        }
+    }
+}
+
+/// Keeps the distance from the target (e.g. archers and casters)
+#[derive(Debug, Clone)]
+pub struct KeepDistance {
+    pub dist: u32,
+}
+
+impl Brain for KeepDistance {
+    fn decide(&self, cx: &mut AiContext) -> Action {
+        let target = match cx.target() {
+            Some(target) => target,
+            None => return Wander.decide(cx),
+        };
+
+        let dist = (target.pos - cx.me.pos).len_king();
+        if dist < self.dist {
+            cx.flee_from(target.pos)
+        } else if dist > self.dist {
+            cx.chase(&target)
+        } else {
+            Action::Rest
+        }
+    }
+}
+
+/// Chases targets within the radius from home, and goes back home otherwise
+#[derive(Debug, Clone)]
+pub struct Guard {
+    pub radius: u32,
+}
+
+impl Brain for Guard {
+    fn decide(&self, cx: &mut AiContext) -> Action {
+        let me = cx.me;
+
+        match cx.target() {
+            Some(target) if (target.pos - me.home).len_king() <= self.radius => cx.chase(&target),
+            _ if me.pos != me.home => cx.approach(me.home),
+            _ => Action::Rest,
+        }
+    }
+}
+
+/// Chases the target, but runs away while HP is low
+#[derive(Debug, Clone)]
+pub struct FleeAtLowHp {
+    /// Ratio to the max HP
+    pub ratio: f32,
+}
+
+impl Brain for FleeAtLowHp {
+    fn decide(&self, cx: &mut AiContext) -> Action {
+        let is_low = (cx.me.hp as f32) < cx.max_hp as f32 * self.ratio;
+
+        match cx.target() {
+            Some(target) if is_low => cx.flee_from(target.pos),
+            _ => Chase.decide(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rlbox::rl::ascii::{self, AsciiMap};
+
+    use super::*;
+
+    fn body(pos: Vec2i, relation: Relation) -> Body {
+        Body {
+            pos,
+            home: pos,
+            hp: 10,
+            view: 8,
+            relation,
+        }
+    }
+
+    /// Bodies of the player (friendly) and the other actors
+    fn bodies(map: &AsciiMap) -> Vec<Body> {
+        let player = map.player.map(|pos| self::body(pos, Relation::Friendly));
+        let actors = map.actors.iter().map(|a| {
+            let relation = if a.is_hostile {
+                Relation::Hostile
+            } else {
+                Relation::Friendly
+            };
+            self::body(a.pos, relation)
+        });
+        player.into_iter().chain(actors).collect()
+    }
+
+    /// Context of the `nth` body in [`bodies`]
+    fn context<'a>(
+        map: &'a AsciiMap,
+        nth: usize,
+        edit: impl FnOnce(&mut Body),
+        cache: &'a mut AiCache,
+    ) -> AiContext<'a> {
+        let mut others = self::bodies(map);
+        let mut me = others.remove(nth);
+        edit(&mut me);
+
+        AiContext::new(
+            &map.map,
+            None,
+            FovAlgorithm::default(),
+            (me, 10),
+            others,
+            cache,
+        )
+    }
+
+    fn decide(brain: &impl Brain, src: &str, edit: impl FnOnce(&mut Body)) -> Action {
+        let map = ascii::parse(src).unwrap();
+        let mut cache = AiCache::default();
+        let mut cx = self::context(&map, 1, edit, &mut cache);
+        brain.decide(&mut cx)
+    }
+
+    const ROOM: &str = r"
+#########
+#@.....A#
+#########
+";
+
+    #[test]
+    fn test_chase() {
+        assert_eq!(self::decide(&Chase, ROOM, |_| {}), Action::Walk(Dir8::W));
+
+        let next = "
+####
+#@A#
+####
+";
+        assert_eq!(
+            self::decide(&Chase, next, |_| {}),
+            Action::MeleeAttack(Dir8::W)
+        );
+
+        // hidden behind a wall
+        let hidden = "
+#######
+#@.#.A#
+#######
+";
+        assert_eq!(self::decide(&Chase, hidden, |_| {}), Action::Wander);
+    }
+
+    #[test]
+    fn test_dark() {
+        let map = ascii::parse(ROOM).unwrap();
+        let dark = LightMap::new(map.map.size(), [0.0; 3]);
+        let mut cache = AiCache::default();
+
+        let mut cx = self::context(&map, 1, |_| {}, &mut cache);
+        cx.light = Some(&dark);
+        assert_eq!(Chase.decide(&mut cx), Action::Wander);
+    }
+
+    #[test]
+    fn test_keep_distance() {
+        let brain = KeepDistance { dist: 3 };
+        assert_eq!(self::decide(&brain, ROOM, |_| {}), Action::Walk(Dir8::W));
+
+        let near = "
+#########
+#...@.A.#
+#########
+";
+        assert_eq!(self::decide(&brain, near, |_| {}), Action::Walk(Dir8::E));
+
+        let just = "
+#########
+#..@..A.#
+#########
+";
+        assert_eq!(self::decide(&brain, just, |_| {}), Action::Rest);
+    }
+
+    #[test]
+    fn test_guard() {
+        let brain = Guard { radius: 2 };
+
+        // the player is out of the guarded area
+        assert_eq!(self::decide(&brain, ROOM, |_| {}), Action::Rest);
+
+        let away = |me: &mut Body| me.pos = me.pos + Vec2i::new(-2, 0);
+        assert_eq!(self::decide(&brain, ROOM, away), Action::Walk(Dir8::E));
+
+        let in_area = |me: &mut Body| me.home = Vec2i::new(3, 1);
+        assert_eq!(self::decide(&brain, ROOM, in_area), Action::Walk(Dir8::W));
+    }
+
+    #[test]
+    fn test_flee_at_low_hp() {
+        let brain = FleeAtLowHp { ratio: 0.5 };
+        let room = "
+#########
+#...@.A.#
+#########
+";
+        assert_eq!(self::decide(&brain, room, |_| {}), Action::Walk(Dir8::W));
+
+        let low = |me: &mut Body| me.hp = 4;
+        assert_eq!(self::decide(&brain, room, low), Action::Walk(Dir8::E));
+    }
+
+    #[test]
+    fn test_flee_map_cache() {
+        let map = ascii::parse(ROOM).unwrap();
+        let mut cache = AiCache::default();
+
+        for _ in 0..2 {
+            let mut cx = self::context(&map, 1, |_| {}, &mut cache);
+            let player = cx.target().unwrap();
+            cx.flee_from(player.pos);
+        }
+        assert_eq!(cache.flee_maps.len(), 1);
+
+        cache.clear();
+        assert!(cache.flee_maps.is_empty());
+    }
+}
@@ -7,7 +7,10 @@ They're created referencing rogulike events and then we forget about original ev
 use std::time::Duration;
 
 use {
-    rlbox::rl::grid2d::{Dir8, Vec2i},
+    rlbox::{
+        rl::grid2d::{Dir8, Vec2i},
+        view::map::MapGeom,
+    },
     snow2d::{
         gfx::geom2d::Vec2f,
         ui::{anim::AnimImpl, anim_builder::AnimSeq, Anim as UiAnim, AnimIndex},
@@ -22,9 +25,6 @@ use super::{Anim, AnimResult, Data, Timer};
 /// TODO: rm
 const WALK_SECS: f32 = 8.0 / 60.0;
 
-/// TODO: don't hard code player detection
-const PLAYER: u32 = 0;
-
 #[derive(Debug, Clone, Inspect)]
 pub struct WaitFrames {
     pub frames: usize,
@@ -92,7 +92,7 @@ impl Anim for WalkAnim {
         // be sure to start animation in this frame
         self.timer.set_started(true);
 
-        if self.actors.iter().any(|a| a.slot() == PLAYER) {
+        if self.actors.iter().any(|a| data.world.is_player(*a)) {
             // update Player FoV in this frame
             data.world.shadow.mark_dirty();
         }
@@ -157,10 +157,8 @@ impl Anim for SwingAnim {
 
         // parameters
         let dpos = {
-            let size = Vec2f::new(
-                data.world.map.tiled.tile_width as f32,
-                data.world.map.tiled.tile_height as f32,
-            );
+            let tile_size = data.world.map.tile_size();
+            let size = Vec2f::new(tile_size[0] as f32, tile_size[1] as f32);
             size * Vec2i::from(self.dir).to_vec2f()
         };
         let img_offset = actor.view.img_offset();
@@ -54,7 +54,7 @@ pub fn play_sound_preserve<'a>(sound: impl Into<AssetKey<'a>>, data: &mut Data)
 }
 
 pub fn run_dir_anim(id: impl Into<String>, pos: Vec2i, dir: Dir8, data: &mut Data) {
-    let pos = rlbox::render::tiled::t2w_center(pos, &data.world.map.tiled);
+    let pos = rlbox::render::tiled::t2w_center(pos, &data.world.map);
 
     data.res.dir_anims.add({
         let anim_type = TypeObjectId::<DirAnimType>::from_raw(id.into())
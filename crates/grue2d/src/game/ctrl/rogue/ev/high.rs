@@ -2,17 +2,23 @@
 High level commands
 */
 
+use rand::Rng;
+
 use snow2d::utils::arena::Index;
 
-use rlbox::rl::grid2d::*;
+use rlbox::rl::{grid2d::*, rlmap::DoorState};
 
 use crate::game::{
     ctrl::rogue::{
+        ai,
         anim::{self as rl_anim, *},
         ev,
-        tick::{Event, EventResult, GenAnim},
+        tick::{Event, EventResult, GenAnim, TURN_ENERGY},
+    },
+    data::world::{
+        actor::{Actor, ActorStats},
+        Entities, World,
     },
-    data::world::{actor::Actor, World},
     Data,
 };
 
@@ -26,24 +32,143 @@ pub enum Attack {
     MeleeAttackFromActor { actor: Index<Actor> },
 }
 
+/// Stat-based rules of attacks
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombatRules {
+    /// Hit chance when the attacker's `atk` equals the defender's `def`
+    pub hit_base: f32,
+    /// Hit chance added per point of `atk` over `def`
+    pub hit_per_stat: f32,
+    /// `[min, max]` of hit chance
+    pub hit_range: [f32; 2],
+    /// Damage is multiplied by a random value in range `[1 - variance, 1 + variance]`
+    pub variance: f32,
+    pub crit_chance: f32,
+    /// Damage multiplier of critical hits
+    pub crit_mul: f32,
+    /// Ratio of `def` subtracted from damage. Critical hits ignore defense
+    pub def_reduction: f32,
+}
+
+impl Default for CombatRules {
+    fn default() -> Self {
+        Self {
+            hit_base: 0.8,
+            hit_per_stat: 0.02,
+            hit_range: [0.05, 0.95],
+            variance: 0.2,
+            crit_chance: 0.05,
+            crit_mul: 1.5,
+            def_reduction: 0.5,
+        }
+    }
+}
+
+/// Output of [`CombatRules::resolve`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttackOutcome {
+    Miss,
+    Hit { amount: u32 },
+    CriticalHit { amount: u32 },
+}
+
+impl CombatRules {
+    pub fn hit_chance(&self, atk: &ActorStats, def: &ActorStats) -> f32 {
+        let diff = atk.atk as f32 - def.def as f32;
+        let chance = self.hit_base + self.hit_per_stat * diff;
+        chance.max(self.hit_range[0]).min(self.hit_range[1])
+    }
+
+    /// Rolls an attack. Pass a seeded RNG for reproducible results
+    pub fn resolve(&self, atk: &ActorStats, def: &ActorStats, rng: &mut impl Rng) -> AttackOutcome {
+        if !rng.gen_bool(self.hit_chance(atk, def) as f64) {
+            return AttackOutcome::Miss;
+        }
+
+        let variance = 1.0 + self.variance * rng.gen_range(-1.0f32..=1.0);
+        let raw = atk.atk as f32 * variance;
+
+        if rng.gen_bool(self.crit_chance as f64) {
+            let amount = (raw * self.crit_mul).round().max(1.0) as u32;
+            AttackOutcome::CriticalHit { amount }
+        } else {
+            let amount = (raw - def.def as f32 * self.def_reduction).round().max(1.0) as u32;
+            AttackOutcome::Hit { amount }
+        }
+    }
+
+    /// Rolls an attack and creates the event of the outcome
+    pub fn resolve_event(
+        &self,
+        attacker: Index<Actor>,
+        target: Index<Actor>,
+        entities: &Entities,
+        rng: &mut impl Rng,
+    ) -> Box<dyn Event> {
+        let outcome = self.resolve(&entities[attacker].stats, &entities[target].stats, rng);
+
+        let (amount, is_critical) = match outcome {
+            AttackOutcome::Miss => return Box::new(Miss { target, attacker }),
+            AttackOutcome::Hit { amount } => (amount, false),
+            AttackOutcome::CriticalHit { amount } => (amount, true),
+        };
+
+        Box::new(Hit {
+            target,
+            attacker,
+            amount,
+            is_critical,
+        })
+    }
+}
+
+/// [`Attack`] missed an actor
+#[derive(Debug)]
+pub struct Miss {
+    pub target: Index<Actor>,
+    pub attacker: Index<Actor>,
+}
+
+impl Event for Miss {
+    fn run(&self, _data: &mut Data) -> EventResult {
+        EventResult::Finish
+    }
+}
+
+impl GenAnim for Miss {
+    fn gen_anim(&self, data: &mut Data) -> Option<Box<dyn Anim>> {
+        ev::popup_text("miss", self.target, data);
+        None
+    }
+}
+
 /// [`Attack`] applied to an actor
 #[derive(Debug)]
 pub struct Hit {
     pub target: Index<Actor>,
     pub attacker: Index<Actor>,
+    pub amount: u32,
+    /// Critical hits deal extra damage
+    pub is_critical: bool,
 }
 
 impl Event for Hit {
     fn run(&self, _data: &mut Data) -> EventResult {
         EventResult::chain(GiveDamage {
             target: self.target,
-            amount: 10,
+            amount: self.amount,
+            cause: DamageCause::Attack {
+                attacker: self.attacker,
+            },
         })
     }
 }
 
 impl GenAnim for Hit {
     fn gen_anim(&self, _data: &mut Data) -> Option<Box<dyn Anim>> {
+        if self.is_critical {
+            // TODO: flash the screen
+        }
         None
     }
 }
@@ -58,6 +183,10 @@ impl Event for JustSwing {
     fn run(&self, _data: &mut Data) -> EventResult {
         EventResult::Finish
     }
+
+    fn cost(&self) -> Option<u32> {
+        Some(TURN_ENERGY)
+    }
 }
 
 impl GenAnim for JustSwing {
@@ -105,11 +234,14 @@ impl MeleeAttack {
 impl Event for MeleeAttack {
     fn run(&self, data: &mut Data) -> EventResult {
         if let Some(target) = self.pull_target(&data.world) {
-            // hit entity
-            EventResult::chain(Hit {
+            // hit or miss the entity
+            let ev = data.res.combat.resolve_event(
+                self.actor,
                 target,
-                attacker: self.actor,
-            })
+                &data.world.entities,
+                &mut data.res.rng,
+            );
+            EventResult::Chain(ev)
         } else {
             // just swing and change direction
             match self.dir {
@@ -121,6 +253,10 @@ impl Event for MeleeAttack {
             }
         }
     }
+
+    fn cost(&self) -> Option<u32> {
+        Some(TURN_ENERGY)
+    }
 }
 
 impl GenAnim for MeleeAttack {
@@ -151,16 +287,113 @@ pub struct RandomWalk {
 impl GenAnim for RandomWalk {}
 
 impl Event for RandomWalk {
-    fn run(&self, _data: &mut Data) -> EventResult {
-        let dir = {
-            use rand::Rng;
-            let mut rng = rand::thread_rng();
-            Dir8::CLOCKWISE[rng.gen_range(0..8)]
-        };
+    fn run(&self, data: &mut Data) -> EventResult {
+        let dir = Dir8::CLOCKWISE[data.res.rng.gen_range(0..8)];
 
-        EventResult::chain(PlayerWalk {
+        EventResult::chain(Walk {
             actor: self.actor,
             dir,
         })
     }
 }
+
+/// Walks a step, opening doors on bump. Just changes the direction if the cell is blocked
+#[derive(Debug)]
+pub struct Walk {
+    pub actor: Index<Actor>,
+    pub dir: Dir8,
+}
+
+impl GenAnim for Walk {}
+
+impl Event for Walk {
+    fn run(&self, data: &mut Data) -> EventResult {
+        self::walk_or_bump(&mut data.world, self.actor, self.dir)
+    }
+}
+
+/// Walks a step, opens a door on bump or just changes the direction if the cell is blocked
+///
+/// Shared by [`Walk`] and [`PlayerWalk`].
+pub fn walk_or_bump(world: &mut World, actor: Index<Actor>, dir: Dir8) -> EventResult {
+    let (from_pos, from_dir) = {
+        let actor = &world.entities[actor];
+        (actor.pos, actor.dir)
+    };
+    let pos = from_pos.offset(dir);
+
+    // locked doors are reported by `OpenDoor`
+    if let Some(DoorState::Closed) | Some(DoorState::Locked) = world.map.rlmap().door(pos) {
+        return EventResult::chain(OpenDoor { actor, pos });
+    }
+
+    if world.is_blocked(pos) {
+        EventResult::chain(ChangeDir { actor, dir })
+    } else {
+        EventResult::chain(Move {
+            actor,
+            mcx: MoveContext::Walk,
+            from_pos,
+            to_pos: pos,
+            from_dir,
+            to_dir: dir,
+        })
+    }
+}
+
+/// Turn of an actor controlled by AI (see [`ai`])
+#[derive(Debug)]
+pub struct AiTurn {
+    pub actor: Index<Actor>,
+}
+
+impl GenAnim for AiTurn {}
+
+impl Event for AiTurn {
+    fn run(&self, data: &mut Data) -> EventResult {
+        EventResult::Chain(ai::decide(&mut data.world, self.actor))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn stats(atk: u32, def: u32) -> ActorStats {
+        ActorStats {
+            hp: 10,
+            atk,
+            def,
+            speed: 100,
+            view: 6,
+        }
+    }
+
+    #[test]
+    fn test_combat_rules() {
+        let rules = CombatRules::default();
+        let (strong, weak) = (stats(100, 100), stats(0, 0));
+
+        assert_eq!(rules.hit_chance(&strong, &weak), rules.hit_range[1]);
+        assert_eq!(rules.hit_chance(&weak, &strong), rules.hit_range[0]);
+
+        // same seed, same outcomes
+        let roll = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..32)
+                .map(|_| rules.resolve(&stats(10, 0), &stats(0, 4), &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(roll(0), roll(0));
+
+        for outcome in roll(1) {
+            match outcome {
+                AttackOutcome::Miss => {}
+                AttackOutcome::Hit { amount } => assert!((6..=10).contains(&amount)),
+                AttackOutcome::CriticalHit { amount } => assert!((12..=18).contains(&amount)),
+            }
+        }
+    }
+}
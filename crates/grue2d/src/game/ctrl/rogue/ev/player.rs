@@ -4,7 +4,7 @@ Player events
 
 use snow2d::{input::Key, utils::arena::Index};
 
-use rlbox::rl::grid2d::*;
+use rlbox::rl::{grid2d::*, rlmap::DoorState};
 
 use crate::game::{
     ctrl::rogue::tick::{Event, EventResult, GenAnim},
@@ -53,6 +53,11 @@ impl Event for Interact {
                     dir: Some(self.dir),
                 }),
             }
+        } else if data.world.map.rlmap().door(pos) == Some(DoorState::Open) {
+            EventResult::chain(CloseDoor {
+                actor: self.actor,
+                pos,
+            })
         } else {
             EventResult::chain(JustSwing {
                 actor: self.actor,
@@ -62,7 +67,7 @@ impl Event for Interact {
     }
 }
 
-/// Walk or change direction and chain [`PlayerTurn`]
+/// Walk, open a door or change direction and chain [`PlayerTurn`]
 #[derive(Debug)]
 pub struct PlayerWalk {
     pub actor: Index<Actor>,
@@ -73,30 +78,37 @@ impl GenAnim for PlayerWalk {}
 
 impl Event for PlayerWalk {
     fn run(&self, data: &mut Data) -> EventResult {
-        let (world, vi) = (&mut data.world, &mut data.res.vi);
+        if data.res.vi.turn.is_down() {
+            // rotate only
+            return EventResult::chain(ChangeDir {
+                actor: self.actor,
+                dir: self.dir,
+            });
+        }
 
-        let actor = &mut world.entities[self.actor];
-        let pos = actor.pos + Vec2i::from(self.dir.signs_i32());
-        drop(actor);
+        self::walk_or_bump(&mut data.world, self.actor, self.dir)
+    }
+}
+
+/// Take the stairs under the actor, if any
+#[derive(Debug)]
+pub struct UseStairs {
+    pub actor: Index<Actor>,
+}
 
-        let is_rotate_only = vi.turn.is_down();
+impl GenAnim for UseStairs {}
 
-        if is_rotate_only || world.is_blocked(pos) {
-            EventResult::chain(ChangeDir {
-                actor: self.actor,
-                dir: self.dir,
-            })
-        } else {
-            let actor = &world.entities[self.actor];
+impl Event for UseStairs {
+    fn run(&self, data: &mut Data) -> EventResult {
+        let pos = data.world.entities[self.actor].pos;
 
-            EventResult::chain(Move {
+        match data.world.stairs_at(pos) {
+            Some(stairs) => EventResult::chain(ChangeFloor {
                 actor: self.actor,
-                mcx: MoveContext::Walk,
-                from_pos: actor.pos,
-                to_pos: pos,
-                from_dir: actor.dir,
-                to_dir: self.dir,
-            })
+                to_depth: stairs.to_depth,
+                to_pos: stairs.to_pos,
+            }),
+            None => EventResult::chain(NotConsumeTurn { actor: self.actor }),
         }
     }
 }
@@ -142,6 +154,11 @@ impl Event for PlayerTurn {
         );
 
         if select {
+            let pos = data.world.entities[self.actor].pos;
+            if data.world.stairs_at(pos).is_some() {
+                return EventResult::chain(UseStairs { actor: self.actor });
+            }
+
             return EventResult::chain(Interact {
                 actor: self.actor,
                 dir: data.world.entities[self.actor].dir,
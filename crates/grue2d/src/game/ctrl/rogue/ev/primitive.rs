@@ -4,27 +4,33 @@ also good foor both visualization and separation.
 */
 
 use snow2d::{
-    gfx::geom2d::Vec2f,
+    gfx::{geom2d::Vec2f, Color},
     ui::{anim_builder::AnimGen, node, Node},
     utils::{arena::Index, ez},
 };
 
-use rlbox::rl::grid2d::*;
+use rlbox::rl::{
+    grid2d::*,
+    rlmap::{DoorState, RlMap},
+};
 
 use crate::game::{
     ctrl::rogue::{
         anim::{self, Anim},
-        tick::{Event, EventResult, GenAnim},
+        tick::{Event, EventResult, GenAnim, TURN_ENERGY},
+    },
+    data::{
+        res::UiLayer,
+        world::{
+            actor::{Actor, ActorType, Controller},
+            World,
+        },
     },
-    data::{res::UiLayer, world::actor::Actor},
     Data,
 };
 
 use super::*;
 
-/// TODO: remove the magic number
-const PLAYER: usize = 0;
-
 const FONT_SIZE: f32 = 22.0;
 
 /// (Primitive) Some action resulted in a non-turn consuming action
@@ -38,9 +44,8 @@ pub struct NotConsumeTurn {
 }
 
 impl GenAnim for NotConsumeTurn {
-    fn gen_anim(&self, _data: &mut Data) -> Option<Box<dyn Anim>> {
-        // TODO: don't hard code
-        if self.actor.slot() as usize == PLAYER {
+    fn gen_anim(&self, data: &mut Data) -> Option<Box<dyn Anim>> {
+        if data.world.is_player(self.actor) {
             // wait for one frame so that we won't enter inifinite loop
             Some(Box::new(anim::WaitFrames { frames: 1 }))
         } else {
@@ -50,8 +55,8 @@ impl GenAnim for NotConsumeTurn {
 }
 
 impl Event for NotConsumeTurn {
-    fn run(&self, _data: &mut Data) -> EventResult {
-        if self.actor.slot() as usize == PLAYER {
+    fn run(&self, data: &mut Data) -> EventResult {
+        if data.world.is_player(self.actor) {
             // TODO: require one frame wait
             EventResult::chain(PlayerTurn { actor: self.actor })
         } else {
@@ -75,6 +80,10 @@ impl Event for RestOneTurn {
     fn run(&self, _data: &mut Data) -> EventResult {
         EventResult::Finish
     }
+
+    fn cost(&self) -> Option<u32> {
+        Some(TURN_ENERGY)
+    }
 }
 
 /// (Primitive) Just change the facing direction
@@ -131,7 +140,15 @@ impl Event for Move {
             let actor = &mut data.world.entities[self.actor];
             actor.dir = self.to_dir;
             actor.pos = self.to_pos;
-            EventResult::Finish
+
+            match self::hazard_damage(data.world.map.rlmap(), self.to_pos) {
+                Some(amount) => EventResult::chain(GiveDamage {
+                    target: self.actor,
+                    amount,
+                    cause: DamageCause::Hazard,
+                }),
+                None => EventResult::Finish,
+            }
         } else {
             EventResult::chain(ChangeDir {
                 actor: self.actor,
@@ -139,6 +156,140 @@ impl Event for Move {
             })
         }
     }
+
+    fn cost(&self) -> Option<u32> {
+        match self.mcx {
+            MoveContext::Walk => Some(TURN_ENERGY),
+            MoveContext::Teleport => None,
+        }
+    }
+}
+
+/// Damage on entering the cell. `None` if it's not a hazard
+fn hazard_damage(rlmap: &RlMap, pos: Vec2i) -> Option<u32> {
+    rlmap
+        .terrain(pos)
+        .map(|t| t.hazard_damage)
+        .filter(|amount| *amount > 0)
+}
+
+/// Walkable and unoccupied cell nearest to the target (in king distance). `None` if there's no
+/// such cell
+fn arrival_pos(rlmap: &RlMap, target: Vec2i, is_occupied: impl Fn(Vec2i) -> bool) -> Option<Vec2i> {
+    let [w, h] = rlmap.size();
+    let max_radius = std::cmp::max(w, h) as i32;
+
+    (0..=max_radius).find_map(|r| {
+        Rect2i::new(target - Vec2i::new(r, r), [2 * r as u32 + 1; 2])
+            .iter_pos()
+            .filter(|pos| (*pos - target).len_king() == r as u32)
+            .find(|pos| rlmap.is_walkable(*pos) && !is_occupied(*pos))
+    })
+}
+
+/// (Primitive) Open a closed door
+#[derive(Debug)]
+pub struct OpenDoor {
+    pub actor: Index<Actor>,
+    pub pos: Vec2i,
+}
+
+impl GenAnim for OpenDoor {
+    fn gen_anim(&self, _data: &mut Data) -> Option<Box<dyn Anim>> {
+        // TODO: play sound
+        None
+    }
+}
+
+impl Event for OpenDoor {
+    fn run(&self, data: &mut Data) -> EventResult {
+        let world = &mut data.world;
+
+        match world.map.rlmap().door(self.pos) {
+            Some(DoorState::Closed) => {
+                world.map.set_door(self.pos, DoorState::Open);
+                world.shadow.mark_dirty();
+                EventResult::Finish
+            }
+            Some(DoorState::Locked) => {
+                log::trace!("door at {:?} is locked", self.pos);
+                EventResult::chain(NotConsumeTurn { actor: self.actor })
+            }
+            _ => EventResult::chain(NotConsumeTurn { actor: self.actor }),
+        }
+    }
+
+    fn cost(&self) -> Option<u32> {
+        Some(TURN_ENERGY / 2)
+    }
+}
+
+/// (Primitive) Close an open door unless someone is on it
+#[derive(Debug)]
+pub struct CloseDoor {
+    pub actor: Index<Actor>,
+    pub pos: Vec2i,
+}
+
+impl GenAnim for CloseDoor {
+    fn gen_anim(&self, _data: &mut Data) -> Option<Box<dyn Anim>> {
+        // TODO: play sound
+        None
+    }
+}
+
+impl Event for CloseDoor {
+    fn run(&self, data: &mut Data) -> EventResult {
+        let world = &mut data.world;
+
+        let is_occupied = world.entities.iter().any(|(_ix, e)| e.pos == self.pos);
+        if is_occupied || world.map.rlmap().door(self.pos) != Some(DoorState::Open) {
+            return EventResult::chain(NotConsumeTurn { actor: self.actor });
+        }
+
+        world.map.set_door(self.pos, DoorState::Closed);
+        world.shadow.mark_dirty();
+        EventResult::Finish
+    }
+
+    fn cost(&self) -> Option<u32> {
+        Some(TURN_ENERGY / 2)
+    }
+}
+
+/// Source of damage, reported on death
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageCause {
+    Attack {
+        attacker: Index<Actor>,
+    },
+    /// Harmful terrain
+    Hazard,
+}
+
+impl DamageCause {
+    /// Description of death by this cause
+    pub fn describe(&self, world: &World) -> String {
+        match self {
+            Self::Attack { attacker } => {
+                let name = world
+                    .entities
+                    .get(*attacker)
+                    .and_then(|a| ActorType::from_type_key(&a.type_id).ok())
+                    .map(|t| t.name.clone());
+                self::killed_by(name.as_deref())
+            }
+            Self::Hazard => "Died from a hazard".to_string(),
+        }
+    }
+}
+
+/// Description of death by an attack. Unknown or unnamed attackers are `something`
+fn killed_by(attacker_name: Option<&str>) -> String {
+    let name = attacker_name
+        .filter(|name| !name.is_empty())
+        .unwrap_or("something");
+    format!("Killed by {}", name)
 }
 
 /// (Primitive) Change actor's HP
@@ -146,32 +297,37 @@ impl Event for Move {
 pub struct GiveDamage {
     pub target: Index<Actor>,
     pub amount: u32,
+    pub cause: DamageCause,
 }
 
-impl GenAnim for GiveDamage {
-    fn gen_anim(&self, data: &mut Data) -> Option<Box<dyn Anim>> {
-        let ui = &mut data.res.ui;
-        let actor = &data.world.entities[self.target];
+/// Pops up text over the actor (e.g. damage)
+pub fn popup_text(text: impl Into<String>, actor: Index<Actor>, data: &mut Data) {
+    let ui = &mut data.res.ui;
+    let actor = &data.world.entities[actor];
 
-        let base_pos = ui.nodes[&actor.nodes.base].params.pos;
-
-        let text = ui.nodes.add({
-            let mut text = Node::from({
-                let mut text =
-                    node::Text::builder(format!("{}", self.amount), &data.ice.snow.fontbook.tex);
-                text.fontsize(FONT_SIZE).ln_space(2.0).origin([0.5, 0.5]);
-                text.build()
-            });
+    let base_pos = ui.nodes[&actor.nodes.base].params.pos;
 
-            text.layer = UiLayer::OnShadow.to_layer();
-            // FIXME: set font texture size and align
-            text.params.pos = base_pos - Vec2f::new(20.0, 20.0);
-            text
+    let text = ui.nodes.add({
+        let mut text = Node::from({
+            let mut text = node::Text::builder(text.into(), &data.ice.snow.fontbook.tex);
+            text.fontsize(FONT_SIZE).ln_space(2.0).origin([0.5, 0.5]);
+            text.build()
         });
 
-        let mut gen = AnimGen::default();
-        gen.node(&text).dt(ez::EasedDt::linear(1.0));
-        ui.anims.insert(gen.alpha([0, 255]));
+        text.layer = UiLayer::OnShadow.to_layer();
+        // FIXME: set font texture size and align
+        text.params.pos = base_pos - Vec2f::new(20.0, 20.0);
+        text
+    });
+
+    let mut gen = AnimGen::default();
+    gen.node(&text).dt(ez::EasedDt::linear(1.0));
+    ui.anims.insert(gen.alpha([0, 255]));
+}
+
+impl GenAnim for GiveDamage {
+    fn gen_anim(&self, data: &mut Data) -> Option<Box<dyn Anim>> {
+        self::popup_text(format!("{}", self.amount), self.target, data);
 
         // FIXME: the delay should be decided externally. delay the hit anim creation itself
         let se = data
@@ -196,7 +352,10 @@ impl Event for GiveDamage {
             EventResult::Finish
         } else {
             actor.stats.hp = 0;
-            EventResult::Chain(Box::new(Death { actor: self.target }))
+            EventResult::Chain(Box::new(Death {
+                actor: self.target,
+                cause: self.cause,
+            }))
         }
     }
 }
@@ -205,6 +364,7 @@ impl Event for GiveDamage {
 #[derive(Debug)]
 pub struct Death {
     pub actor: Index<Actor>,
+    pub cause: DamageCause,
 }
 
 impl GenAnim for Death {
@@ -226,15 +386,187 @@ impl Event for Death {
     fn run(&self, data: &mut Data) -> EventResult {
         log::trace!("actor at slot {:?} died", self.actor.slot());
 
-        if self.actor.slot() == PLAYER as u32 {
-            todo!("implement player death");
+        if data.world.is_player(self.actor) {
+            return EventResult::chain(PlayerDied {
+                actor: self.actor,
+                cause: self.cause.describe(&data.world),
+            });
         }
 
         // NOTE: deleting actors IMMEDIATELY can result in invalid indices.
         //       should we invalidate the actor AFTER finishing animation?
         // let actor = &mut data.world.entities[self.actor];
         data.world.entities.remove(self.actor).unwrap();
+        // the slot can be reused by another actor
+        data.world.memory.forget_entity(&self.actor.slot());
 
         EventResult::Finish
     }
 }
+
+/// The player died. The game state should enter game over
+///
+/// The actor is left on the map without controller.
+#[derive(Debug)]
+pub struct PlayerDied {
+    pub actor: Index<Actor>,
+    /// Description of the cause of death
+    pub cause: String,
+}
+
+impl GenAnim for PlayerDied {}
+
+impl Event for PlayerDied {
+    fn run(&self, data: &mut Data) -> EventResult {
+        log::trace!("player died: {}", self.cause);
+
+        data.world.entities[self.actor].controller = Controller::None;
+
+        EventResult::Finish
+    }
+}
+
+/// (Primitive) Move the player to another floor, storing the current floor
+#[derive(Debug)]
+pub struct ChangeFloor {
+    pub actor: Index<Actor>,
+    pub to_depth: u32,
+    /// Arrival position. `None` to use the stairs under the actor
+    pub to_pos: Option<Vec2i>,
+}
+
+impl GenAnim for ChangeFloor {
+    fn gen_anim(&self, _data: &mut Data) -> Option<Box<dyn Anim>> {
+        // TODO: fade out and in
+        None
+    }
+}
+
+impl Event for ChangeFloor {
+    fn run(&self, data: &mut Data) -> EventResult {
+        let (world, ui) = (&mut data.world, &mut data.res.ui);
+        let from_pos = world.entities[self.actor].pos;
+
+        if self.to_pos.is_none() && world.stairs_at(from_pos).is_none() {
+            log::warn!("no stairs at {:?}", from_pos);
+            return EventResult::chain(NotConsumeTurn { actor: self.actor });
+        }
+
+        let level = match world.dungeon.take_or_gen_level(self.to_depth) {
+            Ok(level) => level,
+            Err(err) => {
+                log::warn!("failed to change floor: {}", err);
+                return EventResult::chain(NotConsumeTurn { actor: self.actor });
+            }
+        };
+
+        // the stairs are linked on generating the destination
+        let to_pos = self
+            .to_pos
+            .or_else(|| world.stairs_at(from_pos).and_then(|s| s.to_pos))
+            .and_then(|target| {
+                self::arrival_pos(level.map.rlmap(), target, |pos| {
+                    level.entities.iter().any(|(_ix, e)| e.pos == pos)
+                })
+            });
+
+        let to_pos = match to_pos {
+            Some(pos) => pos,
+            None => {
+                log::warn!("no place to arrive at depth {}", self.to_depth);
+                world.dungeon.insert_level(self.to_depth, level);
+                return EventResult::chain(NotConsumeTurn { actor: self.actor });
+            }
+        };
+
+        // actors on stored floors are not drawn
+        for (_ix, e) in world.entities.iter().filter(|(ix, _e)| *ix != self.actor) {
+            ui.nodes[&e.nodes.img].params.color = Color::WHITE.with_alpha(0);
+        }
+
+        // the player keeps the index, which is paid energy after this event
+        let prev = world.swap_level(level, self.actor);
+        world.dungeon.enter(self.to_depth, prev);
+
+        let player = &mut world.entities[self.actor];
+        player.pos = to_pos;
+        player.view.warp(player.pos, player.dir);
+
+        world.shadow.mark_dirty();
+
+        EventResult::Finish
+    }
+
+    fn cost(&self) -> Option<u32> {
+        Some(TURN_ENERGY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rlbox::rl::rlmap::Terrain;
+
+    use super::*;
+
+    #[test]
+    fn test_hazard_damage() {
+        let mut map = rlbox::rl::ascii::parse("...").unwrap().map;
+        let lava = map.add_terrain(Terrain {
+            hazard_damage: 3,
+            ..Default::default()
+        });
+        map.set_terrain([1, 0], lava);
+
+        assert_eq!(self::hazard_damage(&map, Vec2i::new(0, 0)), None);
+        assert_eq!(self::hazard_damage(&map, Vec2i::new(1, 0)), Some(3));
+        assert_eq!(self::hazard_damage(&map, Vec2i::new(3, 0)), None);
+    }
+
+    #[test]
+    fn test_arrival_pos() {
+        let map = rlbox::rl::ascii::parse(
+            "
+#####
+#...#
+#.#.#
+#####
+",
+        )
+        .unwrap()
+        .map;
+        let nobody = |_pos: Vec2i| false;
+
+        assert_eq!(
+            self::arrival_pos(&map, Vec2i::new(1, 1), nobody),
+            Some(Vec2i::new(1, 1))
+        );
+
+        // occupied: the nearest free cell in scan order
+        let occupied = |pos: Vec2i| pos == Vec2i::new(1, 1);
+        assert_eq!(
+            self::arrival_pos(&map, Vec2i::new(1, 1), occupied),
+            Some(Vec2i::new(2, 1))
+        );
+
+        // wall or outside of the map
+        assert_eq!(
+            self::arrival_pos(&map, Vec2i::new(2, 2), nobody),
+            Some(Vec2i::new(1, 1))
+        );
+        assert_eq!(
+            self::arrival_pos(&map, Vec2i::new(5, 4), nobody),
+            Some(Vec2i::new(3, 2))
+        );
+
+        // no room
+        let everyone = |_pos: Vec2i| true;
+        assert_eq!(self::arrival_pos(&map, Vec2i::new(1, 1), everyone), None);
+    }
+
+    #[test]
+    fn test_killed_by() {
+        assert_eq!(self::killed_by(Some("Slime")), "Killed by Slime");
+        assert_eq!(self::killed_by(Some("")), "Killed by something");
+        assert_eq!(self::killed_by(None), "Killed by something");
+    }
+}
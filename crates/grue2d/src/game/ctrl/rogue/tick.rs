@@ -3,6 +3,7 @@ Turn-based game loop implemented with generator in unstable Rust
 */
 
 use std::{
+    cmp::Reverse,
     fmt,
     ops::{Generator, GeneratorState},
     pin::Pin,
@@ -16,12 +17,16 @@ use {
 
 use crate::game::{
     ctrl::rogue::{anim::Anim, ev},
-    data::world::actor::Actor,
+    data::world::{
+        actor::{Actor, Controller},
+        Entities,
+    },
     Data,
 };
 
-/// TODO: remove me
-const PLAYER: usize = 0;
+/// Energy required to take a turn. Every actor gains energy of its speed on each tick of the
+/// scheduler
+pub const TURN_ENERGY: u32 = 100;
 
 /// Boxed [generator]
 ///
@@ -96,28 +101,39 @@ impl GameLoop {
 }
 
 /// Internal game loop implemented as a generator
+///
+/// Actors take turns in order of energy, which is accumulated by speed and paid by actions.
 fn game_loop() -> Gen {
     Box::new(|tcx: TickContext| {
-        let mut actor_slot = 0;
-
         loop {
-            let actor_index = match tcx.world.entities.get_by_slot(actor_slot) {
-                Some((index, _actor)) => index,
-                None => continue,
+            let actor_index = match self::next_actor(&tcx.world.entities) {
+                Some(index) => index,
+                None => {
+                    if !self::gain_energy(&mut tcx.as_mut().world.entities) {
+                        // nobody can act. wait for next frame so that we won't freeze
+                        yield TickResult::ProcessingEvent;
+                    }
+                    continue;
+                }
             };
 
             yield TickResult::TakeTurn(actor_index);
 
-            // TODO: do not hard code entity actions
-            let mut ev: Rc<dyn Event> = match actor_index.slot() as usize {
-                PLAYER => Rc::new(ev::PlayerTurn { actor: actor_index }),
-                _ => Rc::new(ev::RandomWalk { actor: actor_index }),
-            };
+            let controller = tcx.world.entities[actor_index].controller;
+            let mut ev = self::turn_event(controller, actor_index);
 
             // process command
             yield TickResult::Event(ev.clone());
 
+            let mut cost = TurnCost::default();
+
             loop {
+                // actions before going back to the player's decision didn't take place
+                if ev.is::<ev::PlayerTurn>() {
+                    cost.reset();
+                }
+                cost.add(ev.cost());
+
                 match ev.run(tcx.as_mut()) {
                     EventResult::GotoNextFrame => {
                         // wait for next frame
@@ -126,8 +142,8 @@ fn game_loop() -> Gen {
                     }
                     EventResult::Finish => {
                         // go to next actor
-                        actor_slot += 1;
-                        actor_slot %= tcx.world.entities.len() as u32;
+                        let cost = cost.total();
+                        self::pay_energy(&mut tcx.as_mut().world.entities, actor_index, cost);
                         break;
                     }
                     EventResult::Chain(new_ev) => {
@@ -143,6 +159,109 @@ fn game_loop() -> Gen {
     })
 }
 
+/// Energy paid for a turn: the most expensive event in the chain, or [`TURN_ENERGY`] if no event
+/// has cost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct TurnCost(Option<u32>);
+
+impl TurnCost {
+    fn add(&mut self, cost: Option<u32>) {
+        self.0 = self.0.max(cost);
+    }
+
+    /// Discards the costs so far
+    fn reset(&mut self) {
+        self.0 = None;
+    }
+
+    fn total(&self) -> u32 {
+        self.0.unwrap_or(TURN_ENERGY)
+    }
+}
+
+/// Creates the command of the actor's turn
+fn turn_event(controller: Controller, actor: Index<Actor>) -> Rc<dyn Event> {
+    match controller {
+        Controller::Player => Rc::new(ev::PlayerTurn { actor }),
+        Controller::Ai => Rc::new(ev::AiTurn { actor }),
+        // scripts move the actor on their own
+        Controller::Scripted | Controller::None => Rc::new(ev::RestOneTurn { actor }),
+    }
+}
+
+/// The actor with the most energy that can take turn (the lowest slot on tie)
+///
+/// Actors without controller never take turns.
+fn next_actor(entities: &Entities) -> Option<Index<Actor>> {
+    self::pick_next(entities.iter().map(|(ix, e)| (ix, ix.slot(), e)))
+}
+
+/// Gives every actor energy of its speed. Returns false if nobody gains energy
+fn gain_energy(entities: &mut Entities) -> bool {
+    self::gain(entities.into_iter().map(|(_ix, e)| e))
+}
+
+fn pay_energy(entities: &mut Entities, actor: Index<Actor>, cost: u32) {
+    // the actor may be dead
+    if let Some(e) = entities.get_mut(actor) {
+        self::pay(e, cost);
+    }
+}
+
+/// What the scheduler reads and writes on actors
+trait Scheduled {
+    fn controller(&self) -> Controller;
+    fn speed(&self) -> u32;
+    fn energy(&self) -> u32;
+    fn energy_mut(&mut self) -> &mut u32;
+}
+
+impl Scheduled for Actor {
+    fn controller(&self) -> Controller {
+        self.controller
+    }
+
+    fn speed(&self) -> u32 {
+        self.stats.speed
+    }
+
+    fn energy(&self) -> u32 {
+        self.energy
+    }
+
+    fn energy_mut(&mut self) -> &mut u32 {
+        &mut self.energy
+    }
+}
+
+/// [`next_actor`] on `(key, slot, actor)`
+fn pick_next<'a, K, T: Scheduled + 'a>(actors: impl Iterator<Item = (K, u32, &'a T)>) -> Option<K> {
+    actors
+        .filter(|(_k, _slot, e)| e.controller() != Controller::None)
+        .filter(|(_k, _slot, e)| e.energy() >= TURN_ENERGY)
+        .max_by_key(|(_k, slot, e)| (e.energy(), Reverse(*slot)))
+        .map(|(k, _slot, _e)| k)
+}
+
+/// [`gain_energy`] on actors
+fn gain<'a, T: Scheduled + 'a>(actors: impl Iterator<Item = &'a mut T>) -> bool {
+    let mut any = false;
+    for e in actors {
+        if e.controller() == Controller::None {
+            continue;
+        }
+        let speed = e.speed();
+        *e.energy_mut() += speed;
+        any |= speed > 0;
+    }
+    any
+}
+
+fn pay(e: &mut impl Scheduled, cost: u32) {
+    let energy = e.energy_mut();
+    *energy = energy.saturating_sub(cost);
+}
+
 // --------------------------------------------------------------------------------
 // Animation
 
@@ -174,6 +293,11 @@ impl EventResult {
 /// TODO: prefer chain-of-responsibility pattern
 pub trait Event: fmt::Debug + Downcast + GenAnim {
     fn run(&self, ecx: &mut Data) -> EventResult;
+
+    /// Energy consumed by the action. [`TURN_ENERGY`] is paid if no event in the turn has cost
+    fn cost(&self) -> Option<u32> {
+        None
+    }
 }
 
 impl_downcast!(Event);
@@ -187,6 +311,10 @@ impl<T: Event + ?Sized> Event for Box<T> {
     fn run(&self, ecx: &mut Data) -> EventResult {
         (**self).run(ecx)
     }
+
+    fn cost(&self) -> Option<u32> {
+        (**self).cost()
+    }
 }
 
 impl<T: GenAnim + ?Sized> GenAnim for Rc<T> {}
@@ -195,4 +323,128 @@ impl<T: Event + ?Sized> Event for Rc<T> {
     fn run(&self, ecx: &mut Data) -> EventResult {
         (**self).run(ecx)
     }
+
+    fn cost(&self) -> Option<u32> {
+        (**self).cost()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct Dummy {
+        controller: Controller,
+        speed: u32,
+        energy: u32,
+    }
+
+    impl Dummy {
+        fn new(controller: Controller, speed: u32) -> Self {
+            Self {
+                controller,
+                speed,
+                energy: 0,
+            }
+        }
+    }
+
+    impl Scheduled for Dummy {
+        fn controller(&self) -> Controller {
+            self.controller
+        }
+
+        fn speed(&self) -> u32 {
+            self.speed
+        }
+
+        fn energy(&self) -> u32 {
+            self.energy
+        }
+
+        fn energy_mut(&mut self) -> &mut u32 {
+            &mut self.energy
+        }
+    }
+
+    /// Runs the scheduler of [`game_loop`] returning slots of the actors taking turns
+    fn schedule(actors: &mut [Dummy], n_turns: usize) -> Vec<usize> {
+        let mut turns = Vec::new();
+
+        while turns.len() < n_turns {
+            let slots = actors.iter().enumerate().map(|(i, e)| (i, i as u32, e));
+            match self::pick_next(slots) {
+                Some(i) => {
+                    turns.push(i);
+                    self::pay(&mut actors[i], TURN_ENERGY);
+                }
+                None => assert!(self::gain(actors.iter_mut())),
+            }
+        }
+
+        turns
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut actors = vec![
+            Dummy::new(Controller::Ai, 100),
+            Dummy::new(Controller::Ai, 200),
+        ];
+
+        let turns = self::schedule(&mut actors, 30);
+        assert_eq!(turns.iter().filter(|i| **i == 1).count(), 20);
+        assert_eq!(turns.iter().filter(|i| **i == 0).count(), 10);
+    }
+
+    #[test]
+    fn test_tie() {
+        let mut actors = vec![
+            Dummy::new(Controller::Ai, 100),
+            Dummy::new(Controller::Player, 100),
+            Dummy::new(Controller::Ai, 100),
+        ];
+
+        // the lowest slot first on tie
+        assert_eq!(self::schedule(&mut actors, 6), vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_turn_cost() {
+        // the most expensive one is paid
+        let mut cost = TurnCost::default();
+        cost.add(None);
+        cost.add(Some(TURN_ENERGY / 2));
+        cost.add(Some(TURN_ENERGY * 2));
+        assert_eq!(cost.total(), TURN_ENERGY * 2);
+
+        // no event has cost
+        assert_eq!(TurnCost::default().total(), TURN_ENERGY);
+
+        // bumping into a locked door (`OpenDoor` -> `NotConsumeTurn` -> `PlayerTurn`) and then
+        // walking
+        let mut cost = TurnCost::default();
+        cost.add(Some(TURN_ENERGY / 2));
+        cost.add(None);
+        cost.reset();
+        cost.add(None);
+        assert_eq!(cost.total(), TURN_ENERGY);
+    }
+
+    #[test]
+    fn test_no_controller() {
+        let mut actors = vec![
+            Dummy::new(Controller::None, 300),
+            Dummy::new(Controller::Ai, 100),
+        ];
+
+        assert_eq!(self::schedule(&mut actors, 3), vec![1, 1, 1]);
+        assert_eq!(actors[0].energy, 0);
+
+        // nobody can act
+        let mut actors = vec![Dummy::new(Controller::None, 100)];
+        assert!(!self::gain(actors.iter_mut()));
+        assert_eq!(self::pick_next(actors.iter().map(|e| ((), 0, e))), None);
+    }
 }
@@ -4,6 +4,7 @@ Resource types specific for SnowRL
 
 use std::time::Duration;
 
+use rand::rngs::StdRng;
 use serde::{Deserialize, Serialize};
 
 use snow2d::{
@@ -15,7 +16,7 @@ use snow2d::{
 
 use rlbox::view::anim::DirAnimState;
 
-use crate::markup::KbdIcons;
+use crate::{game::ctrl::rogue::ev::CombatRules, markup::KbdIcons};
 
 /// TODO: rm
 const REPEAT_FIRST_FRAMES: u64 = 10;
@@ -193,4 +194,8 @@ pub struct Resources {
     pub ui: Ui,
     /// Directional animations over UI nodes
     pub dir_anims: DirAnimRunner,
+    /// Random number generator for game rules. Seed it for reproducible runs
+    pub rng: StdRng,
+    /// Rules of attacks
+    pub combat: CombatRules,
 }
@@ -3,33 +3,64 @@ Game world, internals and states for GUI
 */
 
 pub mod actor;
+pub mod dungeon;
 
-use snow2d::{utils::arena::Arena, Ice};
+use snow2d::{
+    utils::arena::{Arena, Index},
+    Ice,
+};
 
 use rlbox::{
-    rl::grid2d::*,
+    rl::{grid2d::*, rlmap::DoorState, shadow::MapMemory},
     view::{
         camera::{Camera2d, FollowCamera2d},
-        map::TiledRlMap,
+        map::RlMapView,
         shadow::Shadow,
     },
 };
 
-use self::actor::*;
+use crate::game::ctrl::rogue::ai::AiCache;
+
+use self::{actor::*, dungeon::Dungeon};
 
 pub type Entities = Arena<Actor>;
 
+/// Remembered map keyed by actor slots
+pub type WorldMemory = MapMemory<CellMemory, u32, ActorMemory>;
+
+/// Last observed state of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellMemory {
+    pub is_body_blocked: bool,
+    pub is_view_blocked: bool,
+    /// `None` if it's not a door
+    pub door: Option<DoorState>,
+}
+
+/// Last observed state of an actor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActorMemory {
+    pub dir: Dir8,
+    pub relation: Relation,
+}
+
 /// Roguelike game world
 ///
 /// Turn-based game state should be outside of this struct.
 #[derive(Debug)]
 pub struct World {
-    /// Internals and view of game map
-    pub map: TiledRlMap,
+    /// Internals and view of game map (Tiled map or tiled-free map)
+    pub map: RlMapView,
     /// Entities on the map
     pub entities: Entities,
     /// Double buffer of FoV/FoW with interpolation value
     pub shadow: Shadow,
+    /// What the player has seen (updated on shadow calculation)
+    pub memory: WorldMemory,
+    /// Floors other than the current one
+    pub dungeon: Dungeon,
+    /// Buffers of AI reused over turns
+    pub ai: AiCache,
     /// Where we see
     pub cam: Camera2d,
     /// State for the camera to follow the player
@@ -44,22 +75,74 @@ impl World {
             e.view.update(ice.dt(), e.pos, e.dir);
         }
     }
+
+    /// Remembers cells and actors in the current view. Call it after calculating the shadow
+    pub fn remember_view(&mut self) {
+        let (shadow, rlmap) = (&self.shadow, self.map.rlmap());
+
+        // only lit cells in view are observed
+        let fov = &shadow.fov.a;
+        let r = fov.radius() as i32;
+        let area = Rect2i::new(fov.origin() - Vec2i::new(r, r), [2 * r as u32 + 1; 2]);
+        self.memory.observe_cells(
+            area,
+            |pos| shadow.is_visible(pos),
+            |pos| CellMemory {
+                is_body_blocked: rlmap.is_body_blocked(pos),
+                is_view_blocked: rlmap.is_view_blocked(pos),
+                door: rlmap.door(pos),
+            },
+        );
+
+        let visible = self
+            .entities
+            .iter()
+            .filter(|(_ix, e)| shadow.is_visible(e.pos))
+            .map(|(ix, e)| {
+                let mem = ActorMemory {
+                    dir: e.dir,
+                    relation: e.relation,
+                };
+                (ix.slot(), e.pos, mem)
+            });
+
+        self.memory
+            .observe_entities(|pos| shadow.is_visible(pos), visible);
+    }
 }
 
 /// API
 impl World {
-    pub fn player(&self) -> &Actor {
-        let (ix, _) = self.entities.get_by_slot(0).unwrap();
-        &self.entities[ix]
+    /// The first actor controlled by the player
+    pub fn player_index(&self) -> Option<Index<Actor>> {
+        self.entities
+            .iter()
+            .find(|(_ix, e)| e.controller == Controller::Player)
+            .map(|(ix, _e)| ix)
+    }
+
+    pub fn player(&self) -> Option<&Actor> {
+        let ix = self.player_index()?;
+        Some(&self.entities[ix])
+    }
+
+    pub fn player_mut(&mut self) -> Option<&mut Actor> {
+        let ix = self.player_index()?;
+        Some(&mut self.entities[ix])
     }
 
-    pub fn player_mut(&mut self) -> &mut Actor {
-        let (ix, _) = self.entities.get_by_slot_mut(0).unwrap();
-        &mut self.entities[ix]
+    /// If the actor is alive and controlled by the player
+    pub fn is_player(&self, actor: Index<Actor>) -> bool {
+        self.entities
+            .get(actor)
+            .map_or(false, |e| e.controller == Controller::Player)
     }
 
+    /// True if the cell is not walkable (see [`RlMap::is_walkable`]) or occupied by an actor
+    ///
+    /// [`RlMap::is_walkable`]: rlbox::rl::rlmap::RlMap::is_walkable
     pub fn is_blocked(&mut self, pos: Vec2i) -> bool {
-        if self.map.rlmap.is_body_blocked(pos) {
+        if !self.map.rlmap().is_walkable(pos) {
             return true;
         }
 
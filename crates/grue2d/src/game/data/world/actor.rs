@@ -15,7 +15,10 @@ use snow2d::{
 
 use rlbox::{
     rl::grid2d::*,
-    view::actor::{ActorImage, ActorImageType, ActorNodes},
+    view::{
+        actor::{ActorImage, ActorImageType, ActorNodes},
+        map::ActorPlacement,
+    },
 };
 
 use crate::game::data::{res::UiLayer, world::World};
@@ -23,13 +26,19 @@ use crate::game::data::{res::UiLayer, world::World};
 /// Internal and view states of an actor
 #[derive(Debug, Clone, Inspect)]
 pub struct Actor {
+    pub type_id: TypeObjectId<ActorType>,
     pub pos: Vec2i,
+    /// Where the actor is spawned (e.g. center of the guarded area)
+    pub home: Vec2i,
     pub dir: Dir8,
     pub stats: ActorStats,
     pub view: ActorImage,
     pub nodes: ActorNodes,
     pub relation: Relation,
+    pub controller: Controller,
     pub interact: Option<Interactable>,
+    /// Accumulated by speed and consumed by actions (see [`crate::game::ctrl::rogue::tick`])
+    pub energy: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Inspect)]
@@ -37,6 +46,22 @@ pub struct ActorStats {
     pub hp: u32,
     pub atk: u32,
     pub def: u32,
+    /// Energy gained per tick. 100 for one action per round
+    #[serde(default = "ActorStats::default_speed")]
+    pub speed: u32,
+    /// FoV radius
+    #[serde(default = "ActorStats::default_view")]
+    pub view: u32,
+}
+
+impl ActorStats {
+    fn default_speed() -> u32 {
+        100
+    }
+
+    fn default_view() -> u32 {
+        6
+    }
 }
 
 /// Relation with player: `Hostile` | `Friendly`
@@ -46,17 +71,60 @@ pub enum Relation {
     Friendly,
 }
 
+/// What decides the actions of an actor on its turn
+///
+/// Control can be swapped at runtime (e.g. possession and charm).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Inspect)]
+pub enum Controller {
+    /// Player input
+    Player,
+    /// AI brain
+    Ai,
+    /// Waits for scripts to move it
+    Scripted,
+    /// Never takes turns
+    None,
+}
+
+/// AI of actors controlled by [`Controller::Ai`] (see [`crate::game::ctrl::rogue::ai`])
+///
+/// Targets are visible actors with the other [`Relation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AiType {
+    /// Walks randomly
+    Wander,
+    /// Approaches and attacks the target
+    Chase,
+    /// Keeps the distance from the target
+    KeepDistance { dist: u32 },
+    /// Chases targets within the radius from home, and goes back home otherwise
+    Guard { radius: u32 },
+    /// Chases the target, but runs away while HP is lower than the ratio to the max HP
+    FleeAtLowHp { ratio: f32 },
+}
+
+impl Default for AiType {
+    fn default() -> Self {
+        Self::Wander
+    }
+}
+
 /// Type object for [`Actor`]
 #[derive(Debug, Clone, Serialize, Deserialize, TypeObject)]
 pub struct ActorType {
+    /// Display name (e.g. cause of death)
+    #[serde(default)]
+    pub name: String,
     pub img: SerdeRepr<ActorImageType>,
     pub stats: ActorStats,
+    #[serde(default)]
+    pub ai: AiType,
 }
 
-/// TODO:
+/// Script played on interaction
 #[derive(Debug, Clone, Inspect)]
 pub struct Interactable {
-    //
+    pub script: String,
 }
 
 // --------------------------------------------------------------------------------
@@ -69,6 +137,8 @@ pub struct ActorSpawn {
     pub pos: Vec2i,
     pub dir: Dir8,
     pub relation: Relation,
+    pub controller: Controller,
+    pub script: Option<String>,
 }
 
 impl ActorSpawn {
@@ -78,9 +148,38 @@ impl ActorSpawn {
             pos: Vec2i::default(),
             dir: Dir8::S,
             relation: Relation::Friendly,
+            controller: Controller::Ai,
+            script: None,
         }
     }
 
+    /// Spawn placed in a Tiled map
+    pub fn from_placement(p: &ActorPlacement) -> anyhow::Result<Self> {
+        let mut spawn = Self::new(TypeObjectId::<ActorType>::from_raw(p.type_key.clone()));
+        spawn.pos(p.pos).dir(p.dir);
+
+        match p.relation.as_deref() {
+            None => {}
+            Some("hostile") => {
+                spawn.hostile();
+            }
+            Some("friendly") => {
+                spawn.friendly();
+            }
+            Some(rel) => anyhow::bail!("unknown relation `{}` of actor `{}`", rel, p.type_key),
+        }
+
+        if let Some(script) = &p.script {
+            spawn.script(script.clone());
+        }
+
+        if p.is_player {
+            spawn.controller(Controller::Player);
+        }
+
+        Ok(spawn)
+    }
+
     pub fn pos(&mut self, pos: impl Into<Vec2i>) -> &mut Self {
         self.pos = pos.into();
         self
@@ -106,6 +205,16 @@ impl ActorSpawn {
         self
     }
 
+    pub fn controller(&mut self, controller: Controller) -> &mut Self {
+        self.controller = controller;
+        self
+    }
+
+    pub fn script(&mut self, script: impl Into<String>) -> &mut Self {
+        self.script = Some(script.into());
+        self
+    }
+
     pub fn spawn(&self, world: &mut World, ui: &mut Ui) -> anyhow::Result<Index<Actor>> {
         let type_ = ActorType::from_type_key(&self.type_id)?;
         let img: ActorImage = type_
@@ -116,13 +225,17 @@ impl ActorSpawn {
         let nodes = ActorNodes::new(ui, UiLayer::Actors.to_layer(), img.sprite());
 
         let mut actor = Actor {
+            type_id: self.type_id.clone(),
             pos: self.pos,
+            home: self.pos,
             dir: self.dir,
             view: img,
             stats: type_.stats.clone(),
             nodes,
             relation: self.relation,
-            interact: None,
+            controller: self.controller,
+            interact: self.script.clone().map(|script| Interactable { script }),
+            energy: 0,
         };
 
         actor.view.warp(self.pos, self.dir);
@@ -0,0 +1,416 @@
+/*!
+Multi-floor dungeon
+
+The current floor lives in [`World`]. Other floors are stored in [`Dungeon`] with their states
+preserved, and swapped with the current floor on [`ChangeFloor`].
+
+[`ChangeFloor`]: crate::game::ctrl::rogue::ev::ChangeFloor
+*/
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use snow2d::{
+    gfx::geom2d::Rect2f,
+    utils::{arena::Index, ez},
+};
+
+use rlbox::{
+    rl::{
+        dun::{DungeonGen, DungeonGenParams, GeneratedLevel},
+        grid2d::*,
+    },
+    view::{
+        autotile::AutotileRules,
+        map::{GidTextureMap, GridRlMap, MapGeom, RlMapView},
+        shadow::Shadow,
+    },
+};
+
+use crate::game::data::world::{actor::Actor, Entities, World, WorldMemory};
+
+/// Cell linking two floors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Stairs {
+    pub pos: Vec2i,
+    pub to_depth: u32,
+    /// `None` until the destination floor is generated
+    pub to_pos: Option<Vec2i>,
+}
+
+/// Floor state kept while the player is on another floor
+#[derive(Debug)]
+pub struct Level {
+    pub map: RlMapView,
+    /// Entities except the player
+    pub entities: Entities,
+    pub shadow: Shadow,
+    pub memory: WorldMemory,
+}
+
+/// Output of [`LevelGen`]
+#[derive(Debug)]
+pub struct GenLevel<L = Level> {
+    pub level: L,
+    /// Where the player arrives from the upper floor
+    pub up_stairs: Vec2i,
+    /// Stairs to the lower floor
+    pub down_stairs: Vec<Vec2i>,
+}
+
+/// Creates floors on first visit
+///
+/// Generated entities don't include the player (see [`Level`]).
+pub trait LevelGen<L = Level>: fmt::Debug {
+    fn gen_level(&mut self, depth: u32) -> anyhow::Result<GenLevel<L>>;
+}
+
+/// [`LevelGen`] with [`DungeonGen`], creating tiled-free maps
+///
+/// Each floor is generated with the seed of `params` plus the depth.
+#[derive(Debug, Clone)]
+pub struct GridLevelGen {
+    pub params: DungeonGenParams,
+    pub tile_size: [u32; 2],
+    pub rules: AutotileRules,
+    pub idmap: GidTextureMap,
+    /// `[radius, max_radius]` of FoV
+    pub fov_radius: [u32; 2],
+    /// Duration of FoV animation in seconds
+    pub fov_secs: f32,
+    pub fov_ease: ez::Ease,
+}
+
+impl LevelGen for GridLevelGen {
+    fn gen_level(&mut self, depth: u32) -> anyhow::Result<GenLevel> {
+        let mut rng = StdRng::seed_from_u64(self.params.seed().wrapping_add(depth as u64));
+        let gen = self.params.gen_with(&mut rng);
+
+        let (up_stairs, down_stairs) = self::gen_stairs(&gen)
+            .ok_or_else(|| anyhow::anyhow!("no room for stairs at depth {}", depth))?;
+
+        let map =
+            GridRlMap::from_level_autotiled(&gen, self.tile_size, &self.rules, self.idmap.clone());
+        let size = map.rlmap.size();
+
+        Ok(GenLevel {
+            level: Level {
+                map: RlMapView::from(map),
+                entities: Entities::with_capacity(20),
+                shadow: Shadow::new(self.fov_radius, size, self.fov_secs, self.fov_ease),
+                memory: WorldMemory::new(size),
+            },
+            up_stairs,
+            down_stairs,
+        })
+    }
+}
+
+/// Up stairs at the first entrance and down stairs at the first exit
+fn gen_stairs(level: &GeneratedLevel) -> Option<(Vec2i, Vec<Vec2i>)> {
+    let up = *level.entrances.first()?;
+    let down = *level.exits.first()?;
+    Some((up, vec![down]))
+}
+
+/// Floors of a run
+#[derive(Debug)]
+pub struct Dungeon<L = Level> {
+    /// Depth of the current floor
+    pub depth: u32,
+    /// Floors other than the current one
+    levels: BTreeMap<u32, L>,
+    /// Stairs of every floor keyed by depth
+    stairs: HashMap<u32, Vec<Stairs>>,
+    /// Creates floors not visited yet
+    pub gen: Option<Box<dyn LevelGen<L>>>,
+}
+
+impl<L> Default for Dungeon<L> {
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            levels: BTreeMap::new(),
+            stairs: HashMap::new(),
+            gen: None,
+        }
+    }
+}
+
+/// Lifecycle
+impl<L> Dungeon<L> {
+    pub fn new(depth: u32) -> Self {
+        Self {
+            depth,
+            ..Default::default()
+        }
+    }
+
+    /// Stores a floor not visited yet (or replaces a stored floor)
+    pub fn insert_level(&mut self, depth: u32, level: L) -> Option<L> {
+        self.levels.insert(depth, level)
+    }
+
+    /// Stores the previous floor and makes the depth current
+    pub fn enter(&mut self, depth: u32, prev: L) {
+        self.levels.insert(self.depth, prev);
+        self.depth = depth;
+    }
+
+    /// Takes the stored floor out, generating it if it's not visited yet
+    ///
+    /// Stairs of a generated floor are linked to the upper floor.
+    pub fn take_or_gen_level(&mut self, depth: u32) -> anyhow::Result<L> {
+        if let Some(level) = self.levels.remove(&depth) {
+            return Ok(level);
+        }
+
+        let gen = self
+            .gen
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("no level at depth {} and no generator", depth))?;
+        let gen = gen.gen_level(depth)?;
+
+        // link the stairs with the upper floor
+        if let Some(upper) = depth.checked_sub(1) {
+            let up_pos = self
+                .stairs(upper)
+                .find(|s| s.to_depth == depth)
+                .map(|s| s.pos);
+            self.add_stairs(
+                depth,
+                Stairs {
+                    pos: gen.up_stairs,
+                    to_depth: upper,
+                    to_pos: up_pos,
+                },
+            );
+            for s in self.stairs.entry(upper).or_default() {
+                if s.to_depth == depth && s.to_pos.is_none() {
+                    s.to_pos = Some(gen.up_stairs);
+                }
+            }
+        }
+
+        for pos in gen.down_stairs {
+            self.add_stairs(
+                depth,
+                Stairs {
+                    pos,
+                    to_depth: depth + 1,
+                    to_pos: None,
+                },
+            );
+        }
+
+        Ok(gen.level)
+    }
+
+    pub fn has_level(&self, depth: u32) -> bool {
+        self.depth == depth || self.levels.contains_key(&depth)
+    }
+}
+
+/// Stairs
+impl<L> Dungeon<L> {
+    pub fn add_stairs(&mut self, depth: u32, stairs: Stairs) {
+        self.stairs.entry(depth).or_default().push(stairs);
+    }
+
+    /// Adds stairs in both directions
+    pub fn link(&mut self, a: (u32, Vec2i), b: (u32, Vec2i)) {
+        self.add_stairs(
+            a.0,
+            Stairs {
+                pos: a.1,
+                to_depth: b.0,
+                to_pos: Some(b.1),
+            },
+        );
+        self.add_stairs(
+            b.0,
+            Stairs {
+                pos: b.1,
+                to_depth: a.0,
+                to_pos: Some(a.1),
+            },
+        );
+    }
+
+    pub fn stairs(&self, depth: u32) -> impl Iterator<Item = &Stairs> + '_ {
+        self.stairs.get(&depth).into_iter().flatten()
+    }
+
+    pub fn stairs_at(&self, depth: u32, pos: Vec2i) -> Option<&Stairs> {
+        self.stairs(depth).find(|s| s.pos == pos)
+    }
+}
+
+/// Floors
+impl World {
+    /// Stairs on the current floor
+    pub fn stairs_at(&self, pos: Vec2i) -> Option<&Stairs> {
+        self.dungeon.stairs_at(self.dungeon.depth, pos)
+    }
+
+    /// Replaces the current floor, returning the previous one
+    ///
+    /// The player stays in the world with the same index, while other actors are moved between
+    /// the floors. The camera is fit to the new map.
+    pub fn swap_level(&mut self, mut level: Level, player: Index<Actor>) -> Level {
+        std::mem::swap(&mut self.map, &mut level.map);
+        std::mem::swap(&mut self.shadow, &mut level.shadow);
+        std::mem::swap(&mut self.memory, &mut level.memory);
+        self.ai.clear();
+
+        let mut next = std::mem::replace(&mut level.entities, Entities::with_capacity(0));
+
+        // `level.memory` is of the previous floor now
+        let prev = self
+            .entities
+            .iter()
+            .filter(|(ix, _e)| *ix != player)
+            .map(|(ix, _e)| ix)
+            .collect::<Vec<_>>();
+        level.entities = Entities::with_capacity(prev.len());
+        self::move_actors(
+            &mut self.entities,
+            &mut level.entities,
+            &mut level.memory,
+            prev,
+        );
+
+        let ixs = next.iter().map(|(ix, _e)| ix).collect::<Vec<_>>();
+        self::move_actors(&mut next, &mut self.entities, &mut self.memory, ixs);
+
+        let (grid_size, tile_size) = (self.map.grid_size(), self.map.tile_size());
+        self.cam_follow.deadzone = Rect2f::new(
+            0.0,
+            0.0,
+            grid_size[0] as f32 * tile_size[0] as f32,
+            grid_size[1] as f32 * tile_size[1] as f32,
+        );
+
+        level
+    }
+}
+
+/// Moves actors to another arena, re-keying the memory of them with new slots
+fn move_actors(
+    from: &mut Entities,
+    to: &mut Entities,
+    memory: &mut WorldMemory,
+    actors: Vec<Index<Actor>>,
+) {
+    // forget every actor first so that new slots don't overwrite memories of old slots
+    let moved = actors
+        .into_iter()
+        .filter_map(|ix| {
+            let actor = from.remove(ix)?;
+            let mem = memory.forget_entity(&ix.slot());
+            Some((to.insert(actor), mem))
+        })
+        .collect::<Vec<_>>();
+
+    for (ix, mem) in moved {
+        if let Some(mem) = mem {
+            memory.remember_entity(ix.slot(), mem.pos, mem.state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, rc::Rc};
+
+    use rlbox::rl::dun::BspGenParams;
+
+    use super::*;
+
+    /// Names floors by depth, counting generations
+    #[derive(Debug, Default)]
+    struct NameGen {
+        n_gens: Rc<Cell<usize>>,
+    }
+
+    impl LevelGen<String> for NameGen {
+        fn gen_level(&mut self, depth: u32) -> anyhow::Result<GenLevel<String>> {
+            self.n_gens.set(self.n_gens.get() + 1);
+            Ok(GenLevel {
+                level: format!("floor {}", depth),
+                up_stairs: Vec2i::new(1, depth as i32),
+                down_stairs: vec![Vec2i::new(2, depth as i32)],
+            })
+        }
+    }
+
+    /// Takes the floor out and stores the current one, as [`ChangeFloor`] does
+    ///
+    /// [`ChangeFloor`]: crate::game::ctrl::rogue::ev::ChangeFloor
+    fn change(dungeon: &mut Dungeon<String>, current: &mut String, depth: u32) {
+        let next = dungeon.take_or_gen_level(depth).unwrap();
+        let prev = std::mem::replace(current, next);
+        dungeon.enter(depth, prev);
+    }
+
+    #[test]
+    fn test_down_and_back() {
+        let n_gens = Rc::new(Cell::new(0));
+        let mut dungeon = Dungeon::<String>::new(0);
+        dungeon.gen = Some(Box::new(NameGen {
+            n_gens: n_gens.clone(),
+        }));
+
+        let mut current = dungeon.take_or_gen_level(0).unwrap();
+        let down = Vec2i::new(2, 0);
+        assert_eq!(dungeon.stairs_at(0, down).unwrap().to_pos, None);
+
+        self::change(&mut dungeon, &mut current, 1);
+        assert_eq!(current, "floor 1");
+        assert_eq!(dungeon.depth, 1);
+        assert_eq!(n_gens.get(), 2);
+
+        // stairs are linked in both directions
+        let up = Vec2i::new(1, 1);
+        assert_eq!(dungeon.stairs_at(0, down).unwrap().to_pos, Some(up));
+        assert_eq!(dungeon.stairs_at(1, up).unwrap().to_pos, Some(down));
+        assert_eq!(dungeon.stairs_at(1, Vec2i::new(2, 1)).unwrap().to_depth, 2);
+
+        // states are kept while the floor is stored
+        current.push_str(" (visited)");
+
+        self::change(&mut dungeon, &mut current, 0);
+        assert_eq!(current, "floor 0");
+        assert_eq!(dungeon.depth, 0);
+        assert!(dungeon.has_level(1));
+
+        // the second floor is restored instead of being generated again
+        self::change(&mut dungeon, &mut current, 1);
+        assert_eq!(current, "floor 1 (visited)");
+        assert_eq!(n_gens.get(), 2);
+        assert_eq!(dungeon.stairs(1).count(), 2);
+    }
+
+    #[test]
+    fn test_no_gen() {
+        let mut dungeon = Dungeon::<String>::new(0);
+        assert!(dungeon.take_or_gen_level(1).is_err());
+    }
+
+    #[test]
+    fn test_gen_stairs() {
+        for seed in 0..8 {
+            let level = BspGenParams::new([48, 32], seed).gen();
+            let (up, down) = self::gen_stairs(&level).unwrap();
+
+            assert_eq!(down.len(), 1);
+            assert_ne!(up, down[0]);
+            assert!(level.cell(up).is_walkable());
+            assert!(level.cell(down[0]).is_walkable());
+        }
+    }
+}
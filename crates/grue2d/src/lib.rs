@@ -108,21 +108,26 @@ impl GrueRl {
     fn post_update(&mut self, dt: Duration) {
         let (data, agents) = (&mut self.data, &mut self.agents);
 
-        // shadow
-        // FIXME: don't hard code player detection
-        const PLAYER_SLOT: u32 = 0;
-        let player = &data.world.entities.get_by_slot(PLAYER_SLOT).unwrap().1;
-        data.world
-            .shadow
-            .post_update(dt, &data.world.map.rlmap, player.pos);
-
-        // camera
-        let player_pos = player.view.pos_world_centered(&data.world.map.tiled);
-        data.world.cam_follow.update_follow(
-            &mut data.world.cam,
-            player_pos,
-            Vec2f::from(data.ice.snow.window.size_f32()),
-        );
+        // shadow and camera follow the player (if any)
+        if let Some(player) = data.world.player_index() {
+            let player_cell = data.world.entities[player].pos;
+            let is_dirty = data.world.shadow.is_dirty;
+            data.world
+                .shadow
+                .post_update(dt, data.world.map.rlmap(), player_cell);
+            if is_dirty {
+                data.world.remember_view();
+            }
+
+            let player_pos = data.world.entities[player]
+                .view
+                .pos_world_centered(&data.world.map);
+            data.world.cam_follow.update_follow(
+                &mut data.world.cam,
+                player_pos,
+                Vec2f::from(data.ice.snow.window.size_f32()),
+            );
+        }
 
         agents.world_render.post_update(&data.world, dt);
 
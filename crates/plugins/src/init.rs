@@ -84,6 +84,8 @@ pub fn new_game(w: u32, h: u32) -> Result<(Data, Control, Fsm)> {
                 vi: VInput::new(),
                 ui,
                 dir_anims: Default::default(),
+                rng: rand::SeedableRng::from_entropy(),
+                combat: Default::default(),
             },
             cfg: GameConfig {
                 vol: 1.0,
@@ -114,3 +116,22 @@ pub fn new_game(w: u32, h: u32) -> Result<(Data, Control, Fsm)> {
 
     Ok((data, ctrl, fsm))
 }
+
+/// Replaces the world, UI and turn states with fresh ones. Assets are kept
+///
+/// Drop UI nodes of the previous run before calling it.
+pub fn new_run(data: &mut Data, ctrl: &mut Control) -> Result<()> {
+    let window = &data.ice.snow.window;
+    let screen_size = [window.w, window.h];
+
+    let mut ui = Ui::new();
+    data.world = init_res::init_world(screen_size, &mut data.ice, &mut ui)?;
+
+    data.res.ui = ui;
+    data.res.dir_anims = Default::default();
+    data.res.rng = rand::SeedableRng::from_entropy();
+
+    *ctrl = Control::new();
+
+    Ok(())
+}
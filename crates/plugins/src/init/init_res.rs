@@ -5,17 +5,30 @@ World/resource initialization
 use snow2d::{asset::AssetKey, ui::Ui, utils::tyobj::TypeObjectStorageBuilder, Ice};
 
 use rlbox::{
-    rl::grid2d::*,
+    rl::{
+        dijkstra::DijkstraMap,
+        dun::{BspGenParams, DungeonGenParams},
+        grid2d::Vec2i,
+        path::PathParams,
+    },
     view::{
         actor::ActorImageType,
         anim::DirAnimType,
+        autotile::AutotileRules,
         camera::{Camera2d, FollowCamera2d, TransformParams2d},
-        map::TiledRlMap,
+        map::{MapGeom, RlMapView, TiledRlMap},
         shadow::Shadow,
     },
 };
 
-use grue2d::game::data::world::{actor::*, World};
+use grue2d::game::{
+    ctrl::rogue::ai::AiCache,
+    data::world::{
+        actor::*,
+        dungeon::{Dungeon, GridLevelGen, Stairs},
+        World, WorldMemory,
+    },
+};
 
 use crate::prelude::*;
 
@@ -76,10 +89,11 @@ pub fn load_fonts(ice: &mut Ice) -> Fonts {
 }
 
 pub fn init_world(screen_size: [u32; 2], ice: &mut Ice, ui: &mut Ui) -> anyhow::Result<World> {
-    let map = TiledRlMap::new(paths::map::tmx::TILES, &mut ice.assets)?;
+    let map = RlMapView::from(TiledRlMap::new(paths::map::tmx::TILES, &mut ice.assets)?);
 
     let radius = [consts::FOV_R, 10];
-    let map_size = map.rlmap.size;
+    let map_size = map.rlmap().size();
+    let tile_size = map.tile_size();
 
     let mut world = World {
         cam: Camera2d {
@@ -97,20 +111,38 @@ pub fn init_world(screen_size: [u32; 2], ice: &mut Ice, ui: &mut Ui) -> anyhow::
             deadzone: Rect2f::new(
                 0.0,
                 0.0,
-                map_size[0] as f32 * map.tiled.tile_width as f32,
-                map_size[1] as f32 * map.tiled.tile_height as f32,
+                map_size[0] as f32 * tile_size[0] as f32,
+                map_size[1] as f32 * tile_size[1] as f32,
             ),
             lerp_speed: 0.1,
             is_moving: false,
         },
         map,
         shadow: Shadow::new(radius, map_size, consts::WALK_SECS, consts::FOV_EASE),
+        memory: WorldMemory::new(map_size),
+        dungeon: Dungeon::new(0),
+        ai: AiCache::default(),
         entities: Arena::with_capacity(20),
     };
 
-    snow2d::asset::with_cache(&mut ice.assets, |_cache| {
-        self::load_actors(&mut world, ui).unwrap();
-    });
+    self::load_actors(&mut world, ui)?;
+
+    // floors below the Tiled map are generated
+    world.dungeon.gen = Some(Box::new(GridLevelGen {
+        params: DungeonGenParams::Bsp(BspGenParams::new(consts::DUNGEON_SIZE, rand::random())),
+        tile_size,
+        // TODO: autotile rules for the tileset
+        rules: AutotileRules { layers: Vec::new() },
+        idmap: world
+            .map
+            .tiled()
+            .map(|map| map.idmap.clone())
+            .unwrap_or_default(),
+        fov_radius: radius,
+        fov_secs: consts::WALK_SECS,
+        fov_ease: consts::FOV_EASE,
+    }));
+    self::place_down_stairs(&mut world)?;
 
     // animate initial FoV:
     world.shadow.mark_dirty();
@@ -120,27 +152,71 @@ pub fn init_world(screen_size: [u32; 2], ice: &mut Ice, ui: &mut Ui) -> anyhow::
     Ok(world)
 }
 
+/// Spawns actors placed in the Tiled map. The default player is spawned if there's no player in
+/// the map (e.g. no `actors` layer)
 fn load_actors(world: &mut World, ui: &mut Ui) -> anyhow::Result<()> {
-    // player
-    // ActorSpawn::new("ika-chan")
-    ActorSpawn::new("mokusei-san")
-        .pos([12, 16])
-        .dir(Dir8::S)
-        .spawn(world, ui)?;
+    let placements = match world.map.tiled() {
+        Some(map) => map.actors.clone(),
+        None => Vec::new(),
+    };
 
-    // non-player characters
-    let mut spawn = ActorSpawn::new("mokusei-san");
+    if !placements.iter().any(|p| p.is_player) {
+        log::warn!("no player in `actors` object layer. spawning the default player");
+        self::spawn_default_player(world, ui)?;
+    }
 
-    spawn
-        .pos([14, 12])
-        .dir(Dir8::W)
-        .friendly()
-        .spawn(world, ui)?;
+    for p in &placements {
+        ActorSpawn::from_placement(p)?.spawn(world, ui)?;
+    }
+
+    Ok(())
+}
+
+/// Places the stairs to the next floor on the walkable cell farthest from the player
+fn place_down_stairs(world: &mut World) -> anyhow::Result<()> {
+    let from = world.player().context("no player to place stairs")?.pos;
+
+    let rlmap = world.map.rlmap();
+    let [w, h] = rlmap.size();
+    let dmap = DijkstraMap::new(rlmap, rlmap.size(), Some(from), &PathParams::default());
+
+    let pos = (0..h as i32)
+        .flat_map(|y| (0..w as i32).map(move |x| Vec2i::new(x, y)))
+        .filter_map(|pos| dmap.get(pos).map(|d| (pos, d)))
+        .max_by_key(|(_pos, d)| *d)
+        .map(|(pos, _d)| pos)
+        .context("no walkable cell for stairs")?;
+
+    let depth = world.dungeon.depth;
+    world.dungeon.add_stairs(
+        depth,
+        Stairs {
+            pos,
+            to_depth: depth + 1,
+            to_pos: None,
+        },
+    );
+
+    Ok(())
+}
 
-    spawn
-        .pos([25, 18])
-        .dir(Dir8::E)
-        .hostile()
+/// Spawns the player at [`consts::DEFAULT_PLAYER_POS`] or the first walkable cell
+fn spawn_default_player(world: &mut World, ui: &mut Ui) -> anyhow::Result<()> {
+    let rlmap = world.map.rlmap();
+    let [w, h] = rlmap.size();
+
+    let pos = Some(Vec2i::from(consts::DEFAULT_PLAYER_POS))
+        .filter(|pos| rlmap.is_walkable(*pos))
+        .or_else(|| {
+            (0..h as i32)
+                .flat_map(|y| (0..w as i32).map(move |x| Vec2i::new(x, y)))
+                .find(|pos| rlmap.is_walkable(*pos))
+        })
+        .context("no walkable cell for the player")?;
+
+    ActorSpawn::new(consts::DEFAULT_PLAYER)
+        .pos(pos)
+        .controller(Controller::Player)
         .spawn(world, ui)?;
 
     Ok(())
@@ -15,6 +15,8 @@ use snow2d::{
     utils::{arena::Index, ez, pool::Handle, tweak::*},
 };
 
+use rlbox::view::map::MapGeom;
+
 use grue2d::game::{
     data::{
         res::UiLayer,
@@ -87,8 +89,8 @@ impl<'a> TalkViewCommand<'a> {
 
     fn base_pos(world: &World, actor: Index<Actor>) -> Vec2f {
         let actor = &world.entities[actor];
-        let mut pos = actor.view.pos_world_centered(&world.map.tiled);
-        pos.y -= world.map.tiled.tile_height as f32;
+        let mut pos = actor.view.pos_world_centered(&world.map);
+        pos.y -= world.map.tile_size()[1] as f32;
         pos
     }
 
@@ -117,7 +119,7 @@ impl<'a> TalkViewCommand<'a> {
         let mut txt_pos = Vec2f::new(win_rect[0], win_rect[1]);
 
         if tcfg.dir == TalkDirection::Down {
-            let h = world.map.tiled.tile_height as f32;
+            let h = world.map.tile_size()[1] as f32;
             // only ballon has origin at [0.5, 0.5]
             baloon_pos.y += h * 1.5;
             txt_pos.y += win_rect[3] + h * 2.0;
@@ -4,11 +4,16 @@ UI scenes
 
 pub mod title;
 
-use snow2d::{ui::Ui, Ice};
+use snow2d::{
+    gfx::{geom2d::Vec2f, Color},
+    ui::{node, Node, Ui},
+    utils::{pool::Handle, tweak::*},
+    Ice,
+};
 
 use rlbox::rl::grid2d::*;
 
-use grue2d::game::data::res::Resources;
+use grue2d::game::data::res::{Resources, UiLayer};
 
 #[derive(Debug, PartialEq)]
 pub struct Title {
@@ -80,3 +85,94 @@ impl Title {
         None
     }
 }
+
+/// Return value of the game over screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameOverChoice {
+    NewRun,
+    Title,
+}
+
+/// Game over screen showing the cause of death and the number of turns
+#[derive(Debug, PartialEq)]
+pub struct GameOver {
+    cursor: usize,
+    /// Cause of death and turns. Held to keep the node alive
+    _summary: Handle<Node>,
+    choices: [Handle<Node>; 2],
+}
+
+impl GameOver {
+    const CHOICES: [(GameOverChoice, &'static str); 2] = [
+        (GameOverChoice::NewRun, "New run"),
+        (GameOverChoice::Title, "Return to title"),
+    ];
+
+    const FONT_SIZE: f32 = 28.0;
+
+    pub fn new(cause: &str, turns: u32, ice: &Ice, ui: &mut Ui) -> Self {
+        let fontbook = &ice.snow.fontbook.tex;
+
+        let mut text = |txt: String, pos: Vec2f| {
+            ui.nodes.add({
+                let mut node = Node::from({
+                    let mut text = node::Text::builder(txt, fontbook);
+                    text.fontsize(Self::FONT_SIZE).ln_space(4.0);
+                    text.build()
+                });
+                node.layer = UiLayer::Screen.to_layer();
+                node.params.pos = pos;
+                node
+            })
+        };
+
+        let summary = text(
+            format!("{}\nin {} turns", cause, turns),
+            [tweak!(120.0), tweak!(200.0)].into(),
+        );
+
+        let choices = [0, 1].map(|i| {
+            text(
+                Self::CHOICES[i].1.to_string(),
+                [tweak!(160.0), tweak!(360.0) + 48.0 * i as f32].into(),
+            )
+        });
+
+        let mut scene = Self {
+            cursor: 0,
+            _summary: summary,
+            choices,
+        };
+        scene.update_colors(ui);
+        scene
+    }
+
+    fn update_colors(&self, ui: &mut Ui) {
+        for (i, node) in self.choices.iter().enumerate() {
+            ui.nodes[node].params.color = if i == self.cursor {
+                Color::WHITE
+            } else {
+                Color::WHITE.with_alpha(96)
+            };
+        }
+    }
+
+    pub fn handle_input(&mut self, res: &mut Resources) -> Option<GameOverChoice> {
+        if let Some(dir) = res.vi.dir.dir4_pressed() {
+            let len = self.choices.len();
+            match dir.y_sign() {
+                Sign::Pos => self.cursor = (self.cursor + 1) % len,
+                Sign::Neg => self.cursor = (self.cursor + len - 1) % len,
+                Sign::Neutral => {}
+            }
+            self.update_colors(&mut res.ui);
+            return None;
+        }
+
+        if res.vi.select.is_pressed() {
+            return Some(Self::CHOICES[self.cursor].0);
+        }
+
+        None
+    }
+}
@@ -20,7 +20,7 @@ use grue2d::{
     },
 };
 
-use crate::{play, prelude::*, utils::paths};
+use crate::{play, prelude::*, scenes::GameOverChoice, utils::paths};
 
 /// Roguelike game state
 #[derive(Debug, Default)]
@@ -37,9 +37,11 @@ impl GameState for Roguelike {
 
             match res {
                 TickResult::TakeTurn(actor) => {
-                    // TODO: don't hard code player detection
-                    const PLAYER_SLOT: u32 = 0;
-                    if actor.slot() == PLAYER_SLOT {
+                    if data.world.is_player(actor) {
+                        ctrl.rogue.turns += 1;
+                        // targets may have moved
+                        data.world.ai.clear();
+
                         // NOTE: if we handle "change direction" animation, it can results in an
                         // infinite loop:
                         // run batched walk animation if it's player's turn
@@ -84,6 +86,15 @@ impl GameState for Roguelike {
                     // FIXME: don't use downcast to handle events
                     let any = (*ev).as_any();
 
+                    if let Some(died) = any.downcast_ref::<ev::PlayerDied>() {
+                        let game_over = GameOver::new(&died.cause, ctrl.rogue.turns, data);
+
+                        return StateReturn::NextFrame(vec![
+                            StateCommand::insert(game_over),
+                            StateCommand::Push(TypeId::of::<GameOver>()),
+                        ]);
+                    }
+
                     if let Some(talk) = any.downcast_ref::<ev::InteractWithActor>() {
                         ctrl.rogue.script_to_play = Some(ScriptRef::Interact {
                             from: talk.from,
@@ -129,13 +140,25 @@ impl GameState for Animation {
 /// Title screen
 #[derive(Debug, PartialEq)]
 pub struct Title {
-    title: crate::scenes::Title,
+    /// `None` while starting a new run
+    title: Option<crate::scenes::Title>,
+    /// Starts a new run on `NewGame` (the world is of the previous run)
+    is_after_run: bool,
 }
 
 impl Title {
     pub fn new(ice: &mut Ice, ui: &mut Ui) -> Self {
         Self {
-            title: crate::scenes::Title::new(ice, ui),
+            title: Some(crate::scenes::Title::new(ice, ui)),
+            is_after_run: false,
+        }
+    }
+
+    /// Title screen returned from game over
+    pub fn after_run(ice: &mut Ice, ui: &mut Ui) -> Self {
+        Self {
+            is_after_run: true,
+            ..Self::new(ice, ui)
         }
     }
 }
@@ -153,8 +176,13 @@ impl GameState for Title {
         // data.ice.music_player.play_song(song);
     }
 
-    fn update(&mut self, data: &mut Data, _ctrl: &mut Control) -> StateReturn {
-        let choice = match self.title.handle_input(&mut data.ice, &mut data.res) {
+    fn update(&mut self, data: &mut Data, ctrl: &mut Control) -> StateReturn {
+        let title = match self.title.as_mut() {
+            Some(title) => title,
+            None => return StateReturn::NextFrame(vec![]),
+        };
+
+        let choice = match title.handle_input(&mut data.ice, &mut data.res) {
             Some(res) => res,
             None => return StateReturn::NextFrame(vec![]),
         };
@@ -162,6 +190,24 @@ impl GameState for Title {
         use crate::scenes::title::Choice::*;
 
         StateReturn::NextFrame(match choice {
+            NewGame if self.is_after_run => {
+                // drop the nodes before replacing the UI
+                self.title = None;
+
+                match crate::init::new_run(data, ctrl) {
+                    // the game loop refers to actors of the previous run
+                    Ok(()) => vec![
+                        StateCommand::PopAndRemove,
+                        StateCommand::insert(Roguelike::default()),
+                    ],
+                    Err(err) => {
+                        log::error!("failed to start a new run: {:?}", err);
+                        self.title =
+                            Some(crate::scenes::Title::new(&mut data.ice, &mut data.res.ui));
+                        vec![]
+                    }
+                }
+            }
             NewGame => vec![StateCommand::PopAndRemove],
             Continue => {
                 println!("unimplemented");
@@ -175,6 +221,67 @@ impl GameState for Title {
     }
 }
 
+/// Game over screen. Starts a new run or returns to the title (falling back to the title if the new
+/// run fails to start)
+#[derive(Debug, PartialEq)]
+pub struct GameOver {
+    /// `None` after the choice was made
+    scene: Option<crate::scenes::GameOver>,
+}
+
+impl GameOver {
+    pub fn new(cause: &str, turns: u32, data: &mut Data) -> Self {
+        Self {
+            scene: Some(crate::scenes::GameOver::new(
+                cause,
+                turns,
+                &data.ice,
+                &mut data.res.ui,
+            )),
+        }
+    }
+}
+
+impl GameState for GameOver {
+    fn update(&mut self, data: &mut Data, ctrl: &mut Control) -> StateReturn {
+        let scene = match self.scene.as_mut() {
+            Some(scene) => scene,
+            None => return StateReturn::NextFrame(vec![]),
+        };
+
+        let choice = match scene.handle_input(&mut data.res) {
+            Some(choice) => choice,
+            None => return StateReturn::NextFrame(vec![]),
+        };
+
+        // drop the nodes before replacing the UI
+        self.scene = None;
+
+        if choice == GameOverChoice::NewRun {
+            match crate::init::new_run(data, ctrl) {
+                // the game loop refers to actors of the previous run
+                Ok(()) => {
+                    return StateReturn::NextFrame(vec![
+                        StateCommand::PopAndRemove,
+                        StateCommand::insert(Roguelike::default()),
+                    ]);
+                }
+                Err(err) => {
+                    log::error!("failed to start a new run: {:?}", err);
+                }
+            }
+        }
+
+        // the new run starts on choosing `NewGame` in the title
+        let title = self::Title::after_run(&mut data.ice, &mut data.res.ui);
+        StateReturn::NextFrame(vec![
+            StateCommand::PopAndRemove,
+            StateCommand::insert(title),
+            StateCommand::Push(TypeId::of::<self::Title>()),
+        ])
+    }
+}
+
 /// Just plays hard-coded script (for now)
 #[derive(Debug, PartialEq)]
 pub struct PlayScript {
@@ -214,9 +321,12 @@ impl GameState for PlayScript {
             ScriptRef::Interact { from, to } => (from.clone(), to.clone()),
         };
 
-        // TODO: allow any script
-        let txt = "OMG!\nYou're too big, Ika-chan.\n\nHallo hallo haa~~♪";
-        let play_text = PlayTalkState::new(data, txt.to_string(), from, to);
+        // TODO: allow any script (not only talk)
+        let txt = match &data.world.entities[to].interact {
+            Some(interact) => interact.script.clone(),
+            None => "OMG!\nYou're too big, Ika-chan.\n\nHallo hallo haa~~♪".to_string(),
+        };
+        let play_text = PlayTalkState::new(data, txt, from, to);
 
         StateReturn::ThisFrame(vec![
             StateCommand::insert(play_text),
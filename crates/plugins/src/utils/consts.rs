@@ -12,6 +12,15 @@ pub const FOV_R: u32 = 5;
 
 pub const FOV_EASE: ez::Ease = ez::Ease::Linear;
 
+/// Actor type of the player when the map doesn't place one
+pub const DEFAULT_PLAYER: &str = "mokusei-san";
+
+/// Position of the player when the map doesn't place one
+pub const DEFAULT_PLAYER_POS: [i32; 2] = [12, 16];
+
+/// Size of generated floors
+pub const DUNGEON_SIZE: [usize; 2] = [48, 32];
+
 pub const DEFAULT_FONT_SIZE: f32 = 22.0;
 pub const DEFAULT_LINE_SPACE: f32 = 4.0;
 
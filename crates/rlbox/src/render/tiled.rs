@@ -5,53 +5,60 @@ Tiled map rendering
 use {
     snow2d::gfx::{draw::*, geom2d::*, Color},
     std::cmp,
-    tiled::LayerData,
 };
 
 use crate::{
     rl::{
-        grid2d::{Rect2i, Vec2i, Vec2u},
+        grid2d::{Grid2d, Rect2i, Vec2i, Vec2u},
+        rlmap::{tiled_grid_bounds, tiled_layer_tiles_in},
         shadow::*,
     },
-    view::map::*,
+    view::{autotile::GidLayer, map::*, shadow::Shadow},
 };
 
 /// World coordinates to tile coordinates flooring remaning pixels in a cell
-pub fn w2t_floor(w: impl Into<Vec2f>, tiled: &tiled::Map) -> Vec2i {
+pub fn w2t_floor(w: impl Into<Vec2f>, geom: &impl MapGeom) -> Vec2i {
     let w = w.into();
-    let x = w.x as u32 / tiled.tile_width;
-    let y = w.y as u32 / tiled.tile_height;
+    let tile_size = geom.tile_size();
+    let x = (w.x / tile_size[0] as f32).floor();
+    let y = (w.y / tile_size[1] as f32).floor();
     Vec2i::new(x as i32, y as i32)
 }
 
 /// World coordinates to tile coordinates rounding up remaning pixels in a cell
-pub fn w2t_round_up(w: impl Into<Vec2f>, tiled: &tiled::Map) -> Vec2i {
+pub fn w2t_round_up(w: impl Into<Vec2f>, geom: &impl MapGeom) -> Vec2i {
     let w = w.into();
-    let x = (w.x as u32 + tiled.tile_width - 1) / tiled.tile_width;
-    let y = (w.y as u32 + tiled.tile_height - 1) / tiled.tile_height;
+    let tile_size = geom.tile_size();
+    let x = (w.x / tile_size[0] as f32).ceil();
+    let y = (w.y / tile_size[1] as f32).ceil();
     Vec2i::new(x as i32, y as i32)
 }
 
 /// Tile coordinates to world coordinates (left-up corner)
-pub fn t2w(pos: impl Into<Vec2i>, tiled: &tiled::Map) -> Vec2f {
+pub fn t2w(pos: impl Into<Vec2i>, geom: &impl MapGeom) -> Vec2f {
     let pos = pos.into();
-    let x = pos.x as f32 * tiled.tile_width as f32;
-    let y = pos.y as f32 * tiled.tile_height as f32;
+    let tile_size = geom.tile_size();
+    let x = pos.x as f32 * tile_size[0] as f32;
+    let y = pos.y as f32 * tile_size[1] as f32;
     Vec2f::new(x, y)
 }
 
 /// Tile coordinates to world coordinates (center)
-pub fn t2w_center(pos: impl Into<Vec2i>, tiled: &tiled::Map) -> Vec2f {
+pub fn t2w_center(pos: impl Into<Vec2i>, geom: &impl MapGeom) -> Vec2f {
     let pos = pos.into();
-    let x = pos.x as f32 * tiled.tile_width as f32 + tiled.tile_width as f32 / 2.0;
-    let y = pos.y as f32 * tiled.tile_height as f32 + tiled.tile_height as f32 / 2.0;
+    let tile_size = geom.tile_size();
+    let x = pos.x as f32 * tile_size[0] as f32 + tile_size[0] as f32 / 2.0;
+    let y = pos.y as f32 * tile_size[1] as f32 + tile_size[1] as f32 / 2.0;
     Vec2f::new(x, y)
 }
 
-pub fn grid_bounds_from_pixel_bounds(tiled: &tiled::Map, bounds: &Rect2f) -> Rect2i {
+/// Cells of the map in the pixel bounds. Empty if the bounds are outside of the map
+pub fn grid_bounds_from_pixel_bounds(geom: &impl MapGeom, bounds: &Rect2f) -> Rect2i {
+    let grid_size = geom.grid_size();
+
     let left_up = {
         // FIXME: w2t_round_up would be enough?
-        let mut pos = w2t_floor(bounds.left_up(), tiled);
+        let mut pos = w2t_floor(bounds.left_up(), geom);
         pos.x = cmp::max(pos.x, 0);
         pos.y = cmp::max(pos.y, 0);
         pos
@@ -59,23 +66,23 @@ pub fn grid_bounds_from_pixel_bounds(tiled: &tiled::Map, bounds: &Rect2f) -> Rec
 
     // right down position of the map + [1, 1]
     let right_down = {
-        let mut pos = w2t_round_up(bounds.right_down(), tiled);
-        pos.x = cmp::min(pos.x, tiled.width as i32);
-        pos.y = cmp::min(pos.y, tiled.height as i32);
+        let mut pos = w2t_round_up(bounds.right_down(), geom);
+        pos.x = cmp::min(pos.x, grid_size[0] as i32);
+        pos.y = cmp::min(pos.y, grid_size[1] as i32);
         pos
     };
 
     let size = [
-        (right_down.x - left_up.x) as u32,
-        (right_down.y - left_up.y) as u32,
+        cmp::max(right_down.x - left_up.x, 0) as u32,
+        cmp::max(right_down.y - left_up.y, 0) as u32,
     ];
 
     Rect2i::new(left_up, size)
 }
 
 /// Returns (ys, xs)
-fn visible_cells_from_px_bounds(px_bounds: &Rect2f, tiled: &tiled::Map) -> ([u32; 2], [u32; 2]) {
-    let grid_bounds = self::grid_bounds_from_pixel_bounds(tiled, px_bounds);
+fn visible_cells_from_px_bounds(px_bounds: &Rect2f, geom: &impl MapGeom) -> ([u32; 2], [u32; 2]) {
+    let grid_bounds = self::grid_bounds_from_pixel_bounds(geom, px_bounds);
     self::visible_cells_from_grid_bounds(&grid_bounds)
 }
 
@@ -102,24 +109,42 @@ pub fn render_tiled(
     idmap: &GidTextureMap,
     px_bounds: impl Into<Rect2f>,
     layer_range: impl std::ops::RangeBounds<i32>,
+) {
+    self::render_tiled_with(draw, tiled, idmap, px_bounds, layer_range, |_pos, gid| gid);
+}
+
+/// Renders a tiled map replacing GIDs with `map_gid(pos, gid)` (e.g. to draw remembered doors)
+pub fn render_tiled_with(
+    draw: &mut impl DrawApi,
+    tiled: &tiled::Map,
+    idmap: &GidTextureMap,
+    px_bounds: impl Into<Rect2f>,
+    layer_range: impl std::ops::RangeBounds<i32>,
+    map_gid: impl Fn(Vec2i, u32) -> u32,
 ) {
     let px_bounds: Rect2f = px_bounds.into();
     let grid_bounds = self::grid_bounds_from_pixel_bounds(tiled, &px_bounds);
     let (ys, xs) = self::visible_cells_from_grid_bounds(&grid_bounds);
 
     for layer in tiled.layers.iter().filter(|l| l.visible) {
-        // [0-9]+
-        let int_name = layer.name.chars().take_while(|p| p.is_digit(10));
-        let number = match int_name.collect::<String>().parse::<i32>() {
-            Ok(num) => num,
-            Err(_err) => continue,
+        let number = match self::layer_number(&layer.name) {
+            Some(num) => num,
+            None => continue,
         };
         if layer_range.contains(&number) {
-            render_tiled_layer(draw, tiled, layer, idmap, ys, xs);
+            render_tiled_layer_with(draw, tiled, layer, idmap, ys, xs, &map_gid);
         }
     }
 }
 
+/// Number at the start of the layer name (`[0-9]+`)
+fn layer_number(name: &str) -> Option<i32> {
+    let int_name = name.chars().take_while(|p| p.is_digit(10));
+    int_name.collect::<String>().parse::<i32>().ok()
+}
+
+/// Renders cells in `ys` and `xs`. Cells of infinite layers are counted from the left-up corner of
+/// [`tiled_grid_bounds`]
 #[inline]
 pub fn render_tiled_layer(
     draw: &mut impl DrawApi,
@@ -129,38 +154,109 @@ pub fn render_tiled_layer(
     ys: [u32; 2],
     xs: [u32; 2],
 ) {
-    let tile_size = Vec2u::new(tiled.tile_width, tiled.tile_height);
-    let tiles = match layer.tiles {
-        LayerData::Finite(ref f) => f,
-        LayerData::Infinite(_) => unimplemented!("tiled map infinite layer"),
+    self::render_tiled_layer_with(draw, tiled, layer, idmap, ys, xs, &|_pos, gid| gid);
+}
+
+/// [`render_tiled_layer`] replacing GIDs with `map_gid(pos, gid)`
+pub fn render_tiled_layer_with(
+    draw: &mut impl DrawApi,
+    tiled: &tiled::Map,
+    layer: &tiled::Layer,
+    idmap: &GidTextureMap,
+    ys: [u32; 2],
+    xs: [u32; 2],
+    map_gid: &impl Fn(Vec2i, u32) -> u32,
+) {
+    let origin = self::tiled_grid_bounds(tiled).left_up();
+    let visible = Rect2i::new([xs[0] as i32, ys[0] as i32], [xs[1] - xs[0], ys[1] - ys[0]]);
+
+    for (pos, tile) in self::tiled_layer_tiles_in(layer, origin, &visible) {
+        let gid = map_gid(pos, tile.gid);
+        self::render_tile(draw, tiled, idmap, gid, [pos.x, pos.y]);
+    }
+}
+
+/// Renders a tile at a position in [`RlMap`](crate::rl::rlmap::RlMap)
+#[inline]
+pub fn render_tile(
+    draw: &mut impl DrawApi,
+    tiled: &tiled::Map,
+    idmap: &GidTextureMap,
+    gid: u32,
+    pos: [i32; 2],
+) {
+    let texture = match idmap.gid_to_tile(gid) {
+        Some(t) => t,
+        None => return,
     };
 
-    let size = Vec2f::new(tile_size.x as f32, tile_size.y as f32);
-    for y in ys[0]..ys[1] {
-        for x in xs[0]..xs[1] {
-            let tile = tiles[y as usize][x as usize];
+    let tile_size = Vec2u::new(tiled.tile_width, tiled.tile_height);
+    draw.sprite(&texture).dst_rect_px((
+        [
+            (pos[0] * tile_size.x as i32) as f32,
+            (pos[1] * tile_size.y as i32) as f32,
+        ],
+        Vec2f::new(tile_size.x as f32, tile_size.y as f32),
+    ));
+}
 
-            let texture = match idmap.gid_to_tile(tile.gid) {
+/// Renders tile layers (e.g. autotiled ones) in a bounds in world coordinates
+pub fn render_gid_layers(
+    draw: &mut impl DrawApi,
+    geom: &impl MapGeom,
+    layers: &[GidLayer],
+    idmap: &GidTextureMap,
+    px_bounds: impl Into<Rect2f>,
+    layer_range: impl std::ops::RangeBounds<i32>,
+) {
+    let px_bounds: Rect2f = px_bounds.into();
+    let (ys, xs) = self::visible_cells_from_px_bounds(&px_bounds, geom);
+
+    for layer in layers {
+        let number = match self::layer_number(&layer.name) {
+            Some(num) => num,
+            None => continue,
+        };
+        if layer_range.contains(&number) {
+            render_gid_layer(draw, geom, layer, idmap, ys, xs);
+        }
+    }
+}
+
+/// Renders cells in `ys` and `xs`, which are ranges in cells (not in sub tiles)
+pub fn render_gid_layer(
+    draw: &mut impl DrawApi,
+    geom: &impl MapGeom,
+    layer: &GidLayer,
+    idmap: &GidTextureMap,
+    ys: [u32; 2],
+    xs: [u32; 2],
+) {
+    let tile_size = geom.tile_size();
+    let div = layer.div;
+    let size = Vec2f::new(
+        tile_size[0] as f32 / div as f32,
+        tile_size[1] as f32 / div as f32,
+    );
+
+    for y in (ys[0] * div)..(ys[1] * div) {
+        for x in (xs[0] * div)..(xs[1] * div) {
+            let texture = match idmap.gid_to_tile(layer.gid([x, y])) {
                 Some(t) => t,
                 None => continue,
             };
 
-            draw.sprite(&texture).dst_rect_px((
-                [
-                    (x as i32 * tile_size.x as i32) as f32,
-                    (y as i32 * tile_size.y as i32) as f32,
-                ],
-                size,
-            ));
+            draw.sprite(&texture)
+                .dst_rect_px(([x as f32 * size.x, y as f32 * size.y], size));
         }
     }
 }
 
 /// Renders FoV
-pub fn render_fov(draw: &mut impl DrawApi, tiled: &tiled::Map, bounds: &Rect2f, fov: &FovData) {
-    let tile_size = Vec2u::new(tiled.tile_width, tiled.tile_height);
+pub fn render_fov(draw: &mut impl DrawApi, geom: &impl MapGeom, bounds: &Rect2f, fov: &FovData) {
+    let tile_size = Vec2u::from(geom.tile_size());
 
-    let (ys, xs) = self::visible_cells_from_px_bounds(bounds, tiled);
+    let (ys, xs) = self::visible_cells_from_px_bounds(bounds, geom);
     for y in ys[0]..ys[1] {
         for x in xs[0]..xs[1] {
             let alpha = self::shadow_alpha_from_fov([x, y], fov);
@@ -172,15 +268,15 @@ pub fn render_fov(draw: &mut impl DrawApi, tiled: &tiled::Map, bounds: &Rect2f,
 /// Renders FoV blending two (for animation)
 pub fn render_fov_blend(
     draw: &mut impl DrawApi,
-    tiled: &tiled::Map,
+    geom: &impl MapGeom,
     bounds: &Rect2f,
     fov_new: &FovData,
     fov_old: &FovData,
     blend_factor_new: f32,
 ) {
-    let tile_size = Vec2u::new(tiled.tile_width, tiled.tile_height);
+    let tile_size = Vec2u::from(geom.tile_size());
 
-    let (ys, xs) = self::visible_cells_from_px_bounds(bounds, tiled);
+    let (ys, xs) = self::visible_cells_from_px_bounds(bounds, geom);
     for y in ys[0]..ys[1] {
         for x in xs[0]..xs[1] {
             let alpha = {
@@ -197,7 +293,7 @@ pub fn render_fov_blend(
 /// Renders FoV and FoW blending two (for animation)
 pub fn render_fov_fow_blend(
     draw: &mut impl DrawApi,
-    tiled: &tiled::Map,
+    geom: &impl MapGeom,
     px_bounds: &Rect2f,
     fov_new: &FovData,
     fov_old: &FovData,
@@ -205,9 +301,9 @@ pub fn render_fov_fow_blend(
     fow_old: &FowData,
     fow_new: &FowData,
 ) {
-    let tile_size = Vec2u::new(tiled.tile_width, tiled.tile_height);
+    let tile_size = Vec2u::from(geom.tile_size());
 
-    let (ys, xs) = self::visible_cells_from_px_bounds(px_bounds, tiled);
+    let (ys, xs) = self::visible_cells_from_px_bounds(px_bounds, geom);
     for y in ys[0]..ys[1] {
         for x in xs[0]..xs[1] {
             let alpha = {
@@ -222,13 +318,51 @@ pub fn render_fov_fow_blend(
     }
 }
 
+/// Renders FoV, FoW and lit area of [`Shadow`] blending the double buffers (for animation)
+///
+/// Dark cells in FoV are rendered like FoW, and lit cells are tinted with the light color.
+pub fn render_shadow_blend(
+    draw: &mut impl DrawApi,
+    geom: &impl MapGeom,
+    px_bounds: &Rect2f,
+    shadow: &Shadow,
+) {
+    let tile_size = Vec2u::from(geom.tile_size());
+    let blend_factor_new = shadow.dt.get();
+
+    let (ys, xs) = self::visible_cells_from_px_bounds(px_bounds, geom);
+    for y in ys[0]..ys[1] {
+        for x in xs[0]..xs[1] {
+            let new = self::shadow_rgba_for_fov_fow_light(
+                [x, y],
+                &shadow.fov.a,
+                &shadow.fow.a,
+                &shadow.light.a,
+            );
+            let old = self::shadow_rgba_for_fov_fow_light(
+                [x, y],
+                &shadow.fov.b,
+                &shadow.fow.b,
+                &shadow.light.b,
+            );
+
+            let mut rgba = [0.0; 4];
+            for i in 0..4 {
+                rgba[i] = new[i] * blend_factor_new + old[i] * (1.0 - blend_factor_new);
+            }
+
+            self::render_shadow_cell_rgba(draw, rgba, [x, y], tile_size);
+        }
+    }
+}
+
 #[inline]
 fn shadow_alpha_from_fov(pos: [u32; 2], fov: &FovData) -> f32 {
     let pos = Vec2i::new(pos[0] as i32, pos[1] as i32);
 
     return if fov.is_in_view(pos.into()) {
         let len = (pos - fov.origin()).len_f32();
-        let x = len / fov.radius() as f32;
+        let x = len / cmp::max(fov.radius(), 1) as f32;
         0.60 * ease_shadow_alpha(x)
     } else {
         0.80
@@ -251,10 +385,10 @@ fn shadow_alpha_for_fov_fow(pos: [u32; 2], fov: &FovData, fow: &FowData) -> f32
 
     return if fov.is_in_view(pos.into()) {
         let len = (pos - fov.origin()).len_f32();
-        let x = len / fov.radius() as f32;
+        let x = len / cmp::max(fov.radius(), 1) as f32;
 
         0.60 * ease_shadow_alpha(x)
-    } else if fow.is_visible([pos.x as usize, pos.y as usize]) {
+    } else if fow.is_visible(pos) {
         0.80
     } else {
         1.00 // TODO: change FoW alpha
@@ -270,12 +404,54 @@ fn shadow_alpha_for_fov_fow(pos: [u32; 2], fov: &FovData, fow: &FowData) -> f32
     }
 }
 
+/// Shadow color in RGBA
+#[inline]
+fn shadow_rgba_for_fov_fow_light(
+    pos: [u32; 2],
+    fov: &FovData,
+    fow: &FowData,
+    light: &LightMap,
+) -> [f32; 4] {
+    let alpha = self::shadow_alpha_for_fov_fow(pos, fov, fow);
+
+    let pos = Vec2i::new(pos[0] as i32, pos[1] as i32);
+    if !fov.is_in_view(pos) {
+        return [0.0, 0.0, 0.0, alpha];
+    }
+
+    // darken to FoW alpha
+    let level = light.level(pos);
+    let alpha = alpha + (0.80 - alpha).max(0.0) * (1.0 - level);
+
+    // tint with the chroma of the light (white light makes black shadow)
+    let c = light.color(pos);
+    let max = c[0].max(c[1]).max(c[2]);
+    if max <= 0.0 {
+        return [0.0, 0.0, 0.0, alpha];
+    }
+    let min = c[0].min(c[1]).min(c[2]);
+    let tint = |x: f32| (x - min) / max * 0.5 * (1.0 - alpha);
+
+    [tint(c[0]), tint(c[1]), tint(c[2]), alpha]
+}
+
 #[inline]
 fn render_shadow_cell(draw: &mut impl DrawApi, alpha: f32, pos: [u32; 2], tile_size: Vec2u) {
-    let alpha_u8 = (255 as f32 * alpha) as u8;
+    self::render_shadow_cell_rgba(draw, [0.0, 0.0, 0.0, alpha], pos, tile_size);
+}
+
+#[inline]
+fn render_shadow_cell_rgba(
+    draw: &mut impl DrawApi,
+    rgba: [f32; 4],
+    pos: [u32; 2],
+    tile_size: Vec2u,
+) {
+    let [r, g, b, a] = rgba;
+    let to_u8 = |x: f32| (255.0 * x.max(0.0).min(1.0)) as u8;
 
     draw.white_dot()
-        .color(Color::rgba(0, 0, 0, alpha_u8))
+        .color(Color::rgba(to_u8(r), to_u8(g), to_u8(b), to_u8(a)))
         .dst_rect_px([
             (
                 (pos[0] as i32 * tile_size.x as i32) as f32,
@@ -291,19 +467,17 @@ fn render_shadow_cell(draw: &mut impl DrawApi, alpha: f32, pos: [u32; 2], tile_s
 /// Renders rectangles to non-blocking cells (TODO: consider FoV)
 pub fn mark_non_blocking_cells(
     draw: &mut impl DrawApi,
-    tiled: &tiled::Map,
-    blocks: &[bool],
+    geom: &impl MapGeom,
+    blocks: &Grid2d<bool>,
     px_bounds: &Rect2f,
 ) {
-    let grid_size = Vec2u::new(tiled.width, tiled.height);
-    let tile_size = Vec2u::new(tiled.tile_width, tiled.tile_height);
+    let tile_size = Vec2u::from(geom.tile_size());
     let rect_size = Vec2f::new(tile_size.x as f32 - 4.0, tile_size.y as f32 - 4.0);
 
-    let (ys, xs) = self::visible_cells_from_px_bounds(px_bounds, tiled);
+    let (ys, xs) = self::visible_cells_from_px_bounds(px_bounds, geom);
     for y in ys[0]..ys[1] {
         for x in xs[0]..xs[1] {
-            let ix = (x + y * grid_size.x) as usize;
-            if blocks[ix] {
+            if blocks[[x as i32, y as i32]] {
                 return;
             }
 
@@ -2,7 +2,11 @@
 Internal utilities for roguelike games
 */
 
+pub mod ascii;
+pub mod dijkstra;
 pub mod dun;
 pub mod grid2d;
+pub mod line;
+pub mod path;
 pub mod rlmap;
 pub mod shadow;
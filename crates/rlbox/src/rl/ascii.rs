@@ -0,0 +1,251 @@
+/*!
+Text format of [`RlMap`] for test fixtures and bug reports
+
+| Char       | Cell                                      |
+|------------|-------------------------------------------|
+| `.`        | Floor                                     |
+| `#`        | Wall (blocks both body and view)          |
+| `=`        | Body block (e.g. window)                  |
+| `%`        | View block (e.g. curtain)                 |
+| `+`        | Closed door                               |
+| `'`        | Open door                                 |
+| `&`        | Locked door                               |
+| `@`        | Player on floor                           |
+| `a` to `z` | Actor on floor (`A` to `Z` for hostile)   |
+
+Rows are separated by newlines. [`render`] outputs what [`parse`] reads, except that actors on cells
+other than floors are rendered as standing on floors.
+*/
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::rl::{
+    grid2d::Vec2i,
+    rlmap::{DoorState, RlMap, Terrain},
+};
+
+/// Actor in [`AsciiMap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AsciiActor {
+    pub pos: Vec2i,
+    /// `a` to `z`
+    pub ch: char,
+    pub is_hostile: bool,
+}
+
+/// [`RlMap`] with actor positions
+#[derive(Debug)]
+pub struct AsciiMap {
+    pub map: RlMap,
+    pub player: Option<Vec2i>,
+    /// Non-player actors in row-major order
+    pub actors: Vec<AsciiActor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AsciiError {
+    #[error("Empty map")]
+    Empty,
+    #[error("Row {row} has {found} cells while the first row has {expected}")]
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Unknown character `{ch}` at {pos:?}")]
+    UnknownChar { ch: char, pos: [i32; 2] },
+    #[error("Second player at {pos:?}")]
+    DuplicatePlayer { pos: [i32; 2] },
+}
+
+/// Parses the text format. Empty lines at the start and the end are ignored
+pub fn parse(src: &str) -> Result<AsciiMap, AsciiError> {
+    let rows = src
+        .lines()
+        .skip_while(|l| l.trim().is_empty())
+        .collect::<Vec<_>>();
+    let n_rows = rows
+        .iter()
+        .rposition(|l| !l.trim().is_empty())
+        .map_or(0, |i| i + 1);
+    let rows = &rows[..n_rows];
+
+    let w = rows.first().ok_or(AsciiError::Empty)?.chars().count();
+    let mut out = AsciiMap {
+        map: RlMap::new([w, rows.len()]),
+        player: None,
+        actors: Vec::new(),
+    };
+    let door = out.map.add_terrain(Terrain::door());
+
+    for (y, row) in rows.iter().enumerate() {
+        let found = row.chars().count();
+        if found != w {
+            return Err(AsciiError::RaggedRow {
+                row: y,
+                expected: w,
+                found,
+            });
+        }
+
+        for (x, ch) in row.chars().enumerate() {
+            let pos = Vec2i::new(x as i32, y as i32);
+            let map = &mut out.map;
+
+            match ch {
+                '.' => {}
+                '#' => {
+                    map.set_blocks(pos, true, true);
+                }
+                '=' => {
+                    map.set_blocks(pos, true, false);
+                }
+                '%' => {
+                    map.set_blocks(pos, false, true);
+                }
+                '+' | '\'' | '&' => {
+                    let state = match ch {
+                        '+' => DoorState::Closed,
+                        '\'' => DoorState::Open,
+                        _ => DoorState::Locked,
+                    };
+                    map.set_terrain(pos, door);
+                    map.set_door(pos, state);
+                }
+                '@' => {
+                    if out.player.is_some() {
+                        return Err(AsciiError::DuplicatePlayer {
+                            pos: [pos.x, pos.y],
+                        });
+                    }
+                    out.player = Some(pos);
+                }
+                'a'..='z' | 'A'..='Z' => out.actors.push(AsciiActor {
+                    pos,
+                    ch: ch.to_ascii_lowercase(),
+                    is_hostile: ch.is_ascii_uppercase(),
+                }),
+                _ => {
+                    return Err(AsciiError::UnknownChar {
+                        ch,
+                        pos: [pos.x, pos.y],
+                    })
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Renders the map in the text format (with a trailing newline)
+pub fn render(map: &RlMap, player: Option<Vec2i>, actors: &[AsciiActor]) -> String {
+    let [w, h] = map.size();
+    let mut s = String::with_capacity((w + 1) * h);
+
+    for y in 0..h as i32 {
+        for x in 0..w as i32 {
+            let pos = Vec2i::new(x, y);
+
+            let ch = if player == Some(pos) {
+                '@'
+            } else if let Some(a) = actors.iter().find(|a| a.pos == pos) {
+                if a.is_hostile {
+                    a.ch.to_ascii_uppercase()
+                } else {
+                    a.ch.to_ascii_lowercase()
+                }
+            } else if let Some(door) = map.door(pos) {
+                match door {
+                    DoorState::Closed => '+',
+                    DoorState::Open => '\'',
+                    DoorState::Locked => '&',
+                }
+            } else {
+                match [map.is_body_blocked(pos), map.is_view_blocked(pos)] {
+                    [true, true] => '#',
+                    [true, false] => '=',
+                    [false, true] => '%',
+                    [false, false] => '.',
+                }
+            };
+
+            s.push(ch);
+        }
+        s.push('\n');
+    }
+
+    s
+}
+
+impl AsciiMap {
+    pub fn parse(src: &str) -> Result<Self, AsciiError> {
+        self::parse(src)
+    }
+
+    pub fn render(&self) -> String {
+        self::render(&self.map, self.player, &self.actors)
+    }
+}
+
+impl fmt::Display for AsciiMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let src = "\
+#########
+#..@..+.#
+#.a..'..#
+#=%.B.&.#
+#########
+";
+
+        let map = AsciiMap::parse(src).unwrap();
+        assert_eq!(map.map.size(), [9, 5]);
+        assert_eq!(map.player, Some(Vec2i::new(3, 1)));
+        assert_eq!(map.actors.len(), 2);
+        assert!(map.actors[1].is_hostile);
+
+        assert!(map.map.is_body_blocked([6, 1]));
+        assert!(!map.map.is_body_blocked([5, 2]));
+        assert_eq!(map.map.door([6, 3]), Some(DoorState::Locked));
+        assert!(map.map.is_body_blocked([1, 3]) && !map.map.is_view_blocked([1, 3]));
+        assert!(!map.map.is_body_blocked([2, 3]) && map.map.is_view_blocked([2, 3]));
+
+        assert_eq!(map.render(), src);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(AsciiMap::parse("\n\n").unwrap_err(), AsciiError::Empty);
+        assert_eq!(
+            AsciiMap::parse("###\n##\n").unwrap_err(),
+            AsciiError::RaggedRow {
+                row: 1,
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            AsciiMap::parse("#?#").unwrap_err(),
+            AsciiError::UnknownChar {
+                ch: '?',
+                pos: [1, 0]
+            }
+        );
+        assert_eq!(
+            AsciiMap::parse("@.@").unwrap_err(),
+            AsciiError::DuplicatePlayer { pos: [2, 0] }
+        );
+    }
+}
@@ -0,0 +1,292 @@
+/*!
+Dijkstra maps: distances from multiple sources
+
+Actors walk downhill on the map to approach the sources (e.g. the player or items). Flee maps made
+with [`DijkstraMap::flee`] lead actors away from the sources, preferring open spaces over corners.
+*/
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::rl::{
+    grid2d::{Dir8, Grid2d, Vec2i},
+    path::{PathParams, WalkMap},
+};
+
+/// Distance values of every cell from sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct DijkstraMap {
+    /// `None` if it's unreachable
+    values: Grid2d<Option<i32>>,
+    /// Rules of moves used for calculation and queries
+    params: PathParams,
+}
+
+/// Lifecycle
+impl DijkstraMap {
+    /// Calculates distances from the sources
+    pub fn new(
+        map: &impl WalkMap,
+        size: [usize; 2],
+        sources: impl IntoIterator<Item = Vec2i>,
+        params: &PathParams,
+    ) -> Self {
+        Self::with_values(map, size, sources.into_iter().map(|pos| (pos, 0)), params)
+    }
+
+    /// Calculates distances from the sources with initial values (lower values are more
+    /// attractive)
+    ///
+    /// Cells with values greater than `params.max_cost` are left unreachable.
+    pub fn with_values(
+        map: &impl WalkMap,
+        size: [usize; 2],
+        sources: impl IntoIterator<Item = (Vec2i, i32)>,
+        params: &PathParams,
+    ) -> Self {
+        let mut dmap = Self {
+            values: Grid2d::new(size, None),
+            params: params.clone(),
+        };
+
+        let mut open = BinaryHeap::new();
+        for (pos, value) in sources {
+            if let Some(old) = dmap.values.get_mut(pos) {
+                if matches!(old, Some(v) if *v <= value) {
+                    continue;
+                }
+                *old = Some(value);
+                open.push(Reverse((value, [pos.x, pos.y])));
+            }
+        }
+
+        while let Some(Reverse((value, pos))) = open.pop() {
+            let pos = Vec2i::from(pos);
+
+            // skip outdated entries
+            if dmap.get(pos) != Some(value) {
+                continue;
+            }
+
+            for dir in Dir8::CLOCKWISE.iter().cloned() {
+                let nb = pos + Vec2i::from(dir);
+                if !dmap.contains(nb) || map.is_blocked(nb) || !params.can_move(map, pos, dir) {
+                    continue;
+                }
+
+                let nb_value = value + (params.step_cost(dir) * map.cost(nb)) as i32;
+                if matches!(params.max_cost, Some(max) if nb_value > max as i32) {
+                    continue;
+                }
+
+                if matches!(dmap.values[nb], Some(v) if v <= nb_value) {
+                    continue;
+                }
+
+                dmap.values[nb] = Some(nb_value);
+                open.push(Reverse((nb_value, [nb.x, nb.y])));
+            }
+        }
+
+        dmap
+    }
+
+    /// Makes a map leading away from the sources
+    ///
+    /// Values are multiplied by `-coeff` and then recalculated, so that walking downhill on the
+    /// result avoids dead ends. `coeff` is typically around `1.2`; larger values make actors bolder
+    /// about passing by the sources.
+    pub fn flee(&self, map: &impl WalkMap, coeff: f32) -> Self {
+        let mut inverted = self.clone();
+        inverted.scale(-coeff);
+
+        let sources = inverted
+            .values
+            .iter_pos()
+            .filter_map(|(pos, v)| v.map(|v| (pos, v)))
+            .collect::<Vec<_>>();
+
+        Self::with_values(map, self.size(), sources, &self.params)
+    }
+}
+
+/// API
+impl DijkstraMap {
+    pub fn size(&self) -> [usize; 2] {
+        self.values.size()
+    }
+
+    pub fn contains(&self, pos: impl Into<Vec2i>) -> bool {
+        self.values.contains(pos)
+    }
+
+    /// Distance value at the position. `None` if it's unreachable or outside of the map
+    pub fn get(&self, pos: impl Into<Vec2i>) -> Option<i32> {
+        self.values.get(pos).cloned().flatten()
+    }
+
+    /// Multiplies every value (rounding)
+    pub fn scale(&mut self, factor: f32) {
+        for v in self.values.iter_mut().flatten() {
+            *v = (*v as f32 * factor).round() as i32;
+        }
+    }
+
+    /// Adds values of another map (e.g. to combine desires)
+    pub fn add(&mut self, other: &Self) {
+        assert_eq!(self.size(), other.size());
+
+        for (v, o) in self.values.iter_mut().zip(other.values.iter()) {
+            *v = match (*v, *o) {
+                (Some(v), Some(o)) => Some(v + o),
+                _ => None,
+            };
+        }
+    }
+
+    /// Direction to the lowest neighbour lower than the position. `None` if it's a local minimum
+    ///
+    /// Diagonal moves follow the rule used for the calculation, regarding unreachable cells as
+    /// blocked.
+    pub fn downhill_dir(&self, pos: impl Into<Vec2i>) -> Option<Dir8> {
+        let pos = pos.into();
+        let mut lowest = self.get(pos)?;
+        let mut res = None;
+
+        for dir in Dir8::CLOCKWISE.iter().cloned() {
+            let value = match self.get(pos + Vec2i::from(dir)) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if value < lowest && self.params.can_move(self, pos, dir) {
+                lowest = value;
+                res = Some(dir);
+            }
+        }
+
+        res
+    }
+}
+
+/// Unreachable cells are blocked
+impl WalkMap for DijkstraMap {
+    fn is_blocked(&self, pos: Vec2i) -> bool {
+        self.get(pos).is_none()
+    }
+
+    fn contains(&self, pos: Vec2i) -> bool {
+        <Self>::contains(self, pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::rlmap::RlMap;
+
+    /// `#` is wall (see [`crate::rl::ascii`])
+    fn map(rows: &[&str]) -> RlMap {
+        crate::rl::ascii::parse(&rows.join("\n")).unwrap().map
+    }
+
+    #[test]
+    fn test_new() {
+        let map = self::map(&[
+            ".......", //
+            "...#...", //
+            ".......", //
+        ]);
+
+        // distances from the nearest source
+        let sources = vec![Vec2i::new(0, 0), Vec2i::new(6, 0)];
+        let dmap = DijkstraMap::new(&map, map.size(), sources, &PathParams::default());
+        assert_eq!(dmap.get([0, 0]), Some(0));
+        assert_eq!(dmap.get([6, 0]), Some(0));
+        assert_eq!(dmap.get([2, 0]), Some(2));
+        assert_eq!(dmap.get([4, 2]), Some(2));
+        assert_eq!(dmap.get([3, 0]), Some(3));
+
+        // walls and outside of the map
+        assert_eq!(dmap.get([3, 1]), None);
+        assert_eq!(dmap.get([-1, 0]), None);
+
+        // `max_cost` leaves far cells unreachable
+        let params = PathParams {
+            max_cost: Some(2),
+            ..Default::default()
+        };
+        let dmap = DijkstraMap::new(&map, map.size(), Some(Vec2i::new(0, 0)), &params);
+        assert_eq!(dmap.get([2, 2]), Some(2));
+        assert_eq!(dmap.get([3, 0]), None);
+    }
+
+    #[test]
+    fn test_downhill_dir() {
+        let map = self::map(&[
+            ".....", //
+            ".#...", //
+            ".....", //
+        ]);
+
+        let dmap = DijkstraMap::new(
+            &map,
+            map.size(),
+            Some(Vec2i::new(0, 0)),
+            &PathParams::default(),
+        );
+        assert_eq!(dmap.downhill_dir([3, 0]), Some(Dir8::W));
+        assert_eq!(dmap.downhill_dir([0, 0]), None);
+        assert_eq!(dmap.downhill_dir([1, 1]), None);
+
+        // no corner cutting around the wall
+        assert_eq!(dmap.get([1, 0]), Some(1));
+        assert_eq!(dmap.downhill_dir([2, 1]), Some(Dir8::N));
+        assert_eq!(dmap.downhill_dir([0, 2]), Some(Dir8::N));
+    }
+
+    #[test]
+    fn test_flee() {
+        // corridor with a dead end on the right
+        let map = self::map(&[
+            "#########", //
+            "........#", //
+            "#########", //
+        ]);
+
+        let dmap = DijkstraMap::new(
+            &map,
+            map.size(),
+            Some(Vec2i::new(2, 1)),
+            &PathParams::default(),
+        );
+        let flee = dmap.flee(&map, 1.2);
+
+        // away from the source
+        assert_eq!(flee.downhill_dir([3, 1]), Some(Dir8::E));
+        assert_eq!(flee.downhill_dir([1, 1]), Some(Dir8::W));
+        assert_eq!(flee.downhill_dir([7, 1]), None);
+        assert_eq!(flee.get([7, 1]), Some(-6));
+        assert_eq!(flee.get([1, 1]), Some(-1));
+    }
+
+    #[test]
+    fn test_scale_add() {
+        let map = self::map(&[
+            ".#...", //
+        ]);
+        let params = PathParams::default();
+
+        let mut a = DijkstraMap::new(&map, map.size(), Some(Vec2i::new(2, 0)), &params);
+        a.scale(1.5);
+        assert_eq!(a.get([3, 0]), Some(2));
+        assert_eq!(a.get([4, 0]), Some(3));
+        assert_eq!(a.get([0, 0]), None);
+
+        let b = DijkstraMap::new(&map, map.size(), Some(Vec2i::new(4, 0)), &params);
+        a.add(&b);
+        assert_eq!(a.get([2, 0]), Some(2));
+        assert_eq!(a.get([3, 0]), Some(3));
+        assert_eq!(a.get([4, 0]), Some(3));
+        assert_eq!(a.get([0, 0]), None);
+    }
+}
@@ -1,7 +1,15 @@
 /*!
 Procedual dungeon generation algorithms
+
+Every generator can output [`GeneratedLevel`] via [`DungeonGen`].
 */
 
+mod bsp;
 mod cave;
+mod level;
+mod region;
 
-pub use cave::ca_gen_cave;
+pub use bsp::{bsp_gen, bsp_gen_with, BspGenParams, BspLevel, CorridorStyle};
+pub use cave::{ca_gen_cave, ca_gen_cave_with, CaveEdge, CaveGenParams};
+pub use level::{CellKind, DungeonGen, DungeonGenParams, GeneratedLevel};
+pub use region::{connect_regions, ConnectPolicy, Connectivity, RegionMap};
@@ -0,0 +1,317 @@
+/*!
+Rooms and corridors generated with binary space partitioning (BSP)
+*/
+
+use {
+    rand::{rngs::StdRng, Rng, SeedableRng},
+    serde::{Deserialize, Serialize},
+};
+
+use crate::rl::grid2d::{Grid2d, Rect2i, Vec2i};
+
+/// Shape of corridors connecting rooms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CorridorStyle {
+    /// One horizontal and one vertical segment
+    LShape,
+    /// Horizontal, vertical and horizontal segments (or vertical, horizontal and vertical ones)
+    ZShape,
+}
+
+/// Parameters of [`bsp_gen`]
+///
+/// The same parameters always generate the same level.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BspGenParams {
+    /// Seed of the random number generator
+    pub seed: u64,
+    /// Width, height
+    pub size: [usize; 2],
+    /// Minimum [w, h] of rooms
+    pub min_room_size: [u32; 2],
+    /// Maximum [w, h] of rooms
+    pub max_room_size: [u32; 2],
+    /// Maximum depth of the partitioning tree
+    pub max_depth: usize,
+    pub corridor: CorridorStyle,
+}
+
+impl BspGenParams {
+    /// Default parameters with given size and seed
+    pub fn new(size: [usize; 2], seed: u64) -> Self {
+        Self {
+            seed,
+            size,
+            min_room_size: [4, 4],
+            max_room_size: [12, 8],
+            max_depth: 5,
+            corridor: CorridorStyle::LShape,
+        }
+    }
+}
+
+/// Output of [`bsp_gen`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BspLevel {
+    /// Floor if it's true, wall if it's false
+    pub cells: Grid2d<bool>,
+    /// Rooms in the order of creation
+    pub rooms: Vec<Rect2i>,
+    /// Corridor cells adjacent to the entrance of rooms
+    pub doors: Vec<Vec2i>,
+}
+
+impl BspLevel {
+    /// Index of the room that contains the position
+    pub fn room_at(&self, pos: impl Into<Vec2i>) -> Option<usize> {
+        let pos = pos.into();
+        self.rooms.iter().position(|r| r.contains(pos))
+    }
+}
+
+/// Generates rooms and corridors with BSP
+///
+/// NOTE: `StdRng` is only reproducible with the same version of `rand`.
+pub fn bsp_gen(params: &BspGenParams) -> BspLevel {
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    self::bsp_gen_with(params, &mut rng)
+}
+
+/// Generates rooms and corridors with user-provided random number generator (`params.seed` is
+/// ignored)
+pub fn bsp_gen_with<R: Rng>(params: &BspGenParams, rng: &mut R) -> BspLevel {
+    let mut gen = BspGen {
+        params,
+        rng,
+        level: BspLevel {
+            cells: Grid2d::new(params.size, false),
+            rooms: Vec::new(),
+            doors: Vec::new(),
+        },
+    };
+
+    // leave the outermost cells as walls
+    let root = Rect2i::new(
+        [1, 1],
+        [
+            (params.size[0] as u32).saturating_sub(2),
+            (params.size[1] as u32).saturating_sub(2),
+        ],
+    );
+    gen.split(&root, 0);
+
+    gen.level
+}
+
+struct BspGen<'a, R: Rng> {
+    params: &'a BspGenParams,
+    rng: &'a mut R,
+    level: BspLevel,
+}
+
+impl<'a, R: Rng> BspGen<'a, R> {
+    /// Minimum [w, h] of leaf that can contain a room surrounded by walls
+    fn min_leaf_size(&self) -> [u32; 2] {
+        [
+            self.params.min_room_size[0] + 2,
+            self.params.min_room_size[1] + 2,
+        ]
+    }
+
+    /// Partitions the area and returns the indices of rooms in it
+    fn split(&mut self, area: &Rect2i, depth: usize) -> Vec<usize> {
+        let min = self.min_leaf_size();
+        let can_split_x = area.w() >= min[0] * 2;
+        let can_split_y = area.h() >= min[1] * 2;
+
+        if depth >= self.params.max_depth || !(can_split_x || can_split_y) {
+            return self.place_room(area).into_iter().collect();
+        }
+
+        // prefer splitting the longer side
+        let split_x = match (can_split_x, can_split_y) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => {
+                if area.w() > area.h() * 5 / 4 {
+                    true
+                } else if area.h() > area.w() * 5 / 4 {
+                    false
+                } else {
+                    self.rng.gen_bool(0.5)
+                }
+            }
+        };
+
+        let (a, b) = if split_x {
+            let w = self.rng.gen_range(min[0]..=(area.w() - min[0]));
+            (
+                Rect2i::new(area.left_up(), [w, area.h()]),
+                Rect2i::new(
+                    [area.left() + w as i32, area.up()],
+                    [area.w() - w, area.h()],
+                ),
+            )
+        } else {
+            let h = self.rng.gen_range(min[1]..=(area.h() - min[1]));
+            (
+                Rect2i::new(area.left_up(), [area.w(), h]),
+                Rect2i::new(
+                    [area.left(), area.up() + h as i32],
+                    [area.w(), area.h() - h],
+                ),
+            )
+        };
+
+        let mut rooms_a = self.split(&a, depth + 1);
+        let rooms_b = self.split(&b, depth + 1);
+
+        // connect sibling partitions
+        if !rooms_a.is_empty() && !rooms_b.is_empty() {
+            let ra = rooms_a[self.rng.gen_range(0..rooms_a.len())];
+            let rb = rooms_b[self.rng.gen_range(0..rooms_b.len())];
+            let from = self::rect_center(&self.level.rooms[ra]);
+            let to = self::rect_center(&self.level.rooms[rb]);
+            self.dig_corridor(from, to);
+        }
+
+        rooms_a.extend(rooms_b);
+        rooms_a
+    }
+
+    fn place_room(&mut self, leaf: &Rect2i) -> Option<usize> {
+        let (min, max) = (self.params.min_room_size, self.params.max_room_size);
+
+        // leave one cell of wall on each side
+        let max_w = std::cmp::min(max[0], leaf.w().saturating_sub(2));
+        let max_h = std::cmp::min(max[1], leaf.h().saturating_sub(2));
+        if max_w < min[0] || max_h < min[1] || max_w == 0 || max_h == 0 {
+            return None;
+        }
+
+        let w = self.rng.gen_range(min[0]..=max_w);
+        let h = self.rng.gen_range(min[1]..=max_h);
+        let x = leaf.left() + 1 + self.rng.gen_range(0..=(leaf.w() - 2 - w)) as i32;
+        let y = leaf.up() + 1 + self.rng.gen_range(0..=(leaf.h() - 2 - h)) as i32;
+
+        let room = Rect2i::new([x, y], [w, h]);
+        for y in room.up()..(room.up() + h as i32) {
+            for x in room.left()..(room.left() + w as i32) {
+                self.dig(Vec2i::new(x, y));
+            }
+        }
+
+        self.level.rooms.push(room);
+        Some(self.level.rooms.len() - 1)
+    }
+
+    fn dig(&mut self, pos: Vec2i) {
+        self.level.cells[pos] = true;
+    }
+
+    fn dig_corridor(&mut self, from: Vec2i, to: Vec2i) {
+        let is_horizontal_first = self.rng.gen_bool(0.5);
+
+        // corners of the corridor
+        let mut points = vec![from];
+        match self.params.corridor {
+            CorridorStyle::LShape => {
+                if is_horizontal_first {
+                    points.push(Vec2i::new(to.x, from.y));
+                } else {
+                    points.push(Vec2i::new(from.x, to.y));
+                }
+            }
+            CorridorStyle::ZShape => {
+                if is_horizontal_first {
+                    let mid = self.rng.gen_range(from.x.min(to.x)..=from.x.max(to.x));
+                    points.push(Vec2i::new(mid, from.y));
+                    points.push(Vec2i::new(mid, to.y));
+                } else {
+                    let mid = self.rng.gen_range(from.y.min(to.y)..=from.y.max(to.y));
+                    points.push(Vec2i::new(from.x, mid));
+                    points.push(Vec2i::new(to.x, mid));
+                }
+            }
+        }
+        points.push(to);
+
+        // walk along the segments
+        let mut pos = from;
+        let mut was_in_room = self.level.room_at(pos).is_some();
+        for target in points.iter().skip(1) {
+            let step = Vec2i::new((target.x - pos.x).signum(), (target.y - pos.y).signum());
+
+            while pos != *target {
+                let prev = pos;
+                pos += step;
+                self.dig(pos);
+
+                // doors are the corridor cells next to the room
+                let is_in_room = self.level.room_at(pos).is_some();
+                if was_in_room && !is_in_room {
+                    self.add_door(pos);
+                } else if !was_in_room && is_in_room {
+                    self.add_door(prev);
+                }
+                was_in_room = is_in_room;
+            }
+        }
+    }
+
+    fn add_door(&mut self, pos: Vec2i) {
+        if !self.level.doors.contains(&pos) {
+            self.level.doors.push(pos);
+        }
+    }
+}
+
+fn rect_center(rect: &Rect2i) -> Vec2i {
+    Vec2i::new(
+        rect.left() + rect.w() as i32 / 2,
+        rect.up() + rect.h() as i32 / 2,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::dun::{Connectivity, RegionMap};
+
+    fn levels() -> impl Iterator<Item = BspLevel> {
+        [CorridorStyle::LShape, CorridorStyle::ZShape]
+            .iter()
+            .flat_map(|style| {
+                (0..16).map(move |seed| {
+                    let mut params = BspGenParams::new([48, 32], seed);
+                    params.corridor = *style;
+                    bsp_gen(&params)
+                })
+            })
+    }
+
+    #[test]
+    fn test_rooms_dont_overlap() {
+        for level in self::levels() {
+            assert!(level.rooms.len() >= 2);
+
+            for (i, a) in level.rooms.iter().enumerate() {
+                for b in &level.rooms[i + 1..] {
+                    assert_eq!(a.intersect(b), None, "{:?} overlaps {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rooms_are_reachable() {
+        for level in self::levels() {
+            let regions = RegionMap::label(&level.cells, Connectivity::Four);
+            assert_eq!(regions.n_regions(), 1);
+
+            for room in &level.rooms {
+                assert!(room.iter_pos().all(|pos| regions.region_at(pos) == Some(0)));
+            }
+        }
+    }
+}
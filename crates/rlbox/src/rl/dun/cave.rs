@@ -7,17 +7,81 @@ Cave generated with cellular automata
 use {
     rand::{
         distributions::{Distribution, Uniform},
-        rngs::ThreadRng,
+        rngs::StdRng,
+        Rng, SeedableRng,
     },
+    serde::{Deserialize, Serialize},
     std::{io, io::prelude::*},
 };
 
-use crate::utils::DoubleSwap;
+use crate::{
+    rl::{dun::region::ConnectPolicy, grid2d::Grid2d},
+    utils::DoubleSwap,
+};
+
+/// How cells outside of the map are counted as neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CaveEdge {
+    /// Outside of the map is considered as floor
+    Floor,
+    /// Outside of the map is considered as wall
+    Wall,
+    /// The map is wrapped around (torus)
+    Wrap,
+}
+
+/// Parameters of [`ca_gen_cave`]
+///
+/// The same parameters always generate the same cave.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CaveGenParams {
+    /// Seed of the random number generator
+    pub seed: u64,
+    /// Width, height
+    pub size: [usize; 2],
+    /// Probability in percent [0, 100] that a cell is initially floor
+    pub prob_init_floor: usize,
+    /// Floor cell becomes wall if it has less than or equal to this number of floor neighbours
+    pub death_limit: usize,
+    /// Wall cell becomes floor if it has more than this number of floor neighbours
+    pub birth_limit: usize,
+    /// Number of cellular automata iterations
+    pub n_steps: usize,
+    /// How to count neighbours on the edges
+    pub edge: CaveEdge,
+    /// How isolated regions are handled when the cave is made into a level (see
+    /// [`DungeonGen`](crate::rl::dun::DungeonGen)). Unused by [`ca_gen_cave`]
+    pub connect: ConnectPolicy,
+}
+
+impl CaveGenParams {
+    /// Default parameters with given size and seed
+    pub fn new(size: [usize; 2], seed: u64) -> Self {
+        Self {
+            seed,
+            size,
+            prob_init_floor: 50,
+            death_limit: 2,
+            birth_limit: 5,
+            n_steps: 20,
+            edge: CaveEdge::Floor,
+            connect: ConnectPolicy::FillSmaller,
+        }
+    }
+}
+
+/// Cellular automata. Floor if it's true, wall if it's false
+///
+/// NOTE: `StdRng` is only reproducible with the same version of `rand`.
+pub fn ca_gen_cave(params: &CaveGenParams) -> Grid2d<bool> {
+    let rng = StdRng::seed_from_u64(params.seed);
+    self::ca_gen_cave_with(params, rng)
+}
 
-/// Cellular automata
-pub fn ca_gen_cave(size: [usize; 2], prob_init_floor: usize, n_steps: usize) -> Vec<bool> {
-    let mut x = CaveGenAdvance::new(size, prob_init_floor);
-    for _ in 0..n_steps {
+/// Cellular automata with user-provided random number generator (`params.seed` is ignored)
+pub fn ca_gen_cave_with<R: Rng>(params: &CaveGenParams, rng: R) -> Grid2d<bool> {
+    let mut x = CaveGenAdvance::new(params.clone(), rng);
+    for _ in 0..params.n_steps {
         x.advance();
     }
 
@@ -25,10 +89,8 @@ pub fn ca_gen_cave(size: [usize; 2], prob_init_floor: usize, n_steps: usize) ->
 }
 
 pub struct CaveMap {
-    /// Floor if it's true, wall if it's false. Indexed as [x + y * width]
-    bufs: DoubleSwap<Vec<bool>>,
-    /// Width, height
-    size: [usize; 2],
+    /// Floor if it's true, wall if it's false
+    bufs: DoubleSwap<Grid2d<bool>>,
 }
 
 impl CaveMap {
@@ -36,11 +98,9 @@ impl CaveMap {
         let out = io::stdout();
         let mut out = out.lock();
 
-        let cells = self.bufs.a();
-        for y in 0..self.size[1] {
-            for x in 0..self.size[0] {
-                let ix = x + y * self.size[0];
-                let c = if cells[ix] { '.' } else { '#' };
+        for row in self.bufs.a().rows() {
+            for cell in row {
+                let c = if *cell { '.' } else { '#' };
                 write!(out, "{}", c)?;
             }
 
@@ -50,25 +110,37 @@ impl CaveMap {
         Ok(())
     }
 
-    fn count_neighbours(map: &[bool], size: [usize; 2], x: i32, y: i32) -> usize {
+    fn count_neighbours(map: &Grid2d<bool>, edge: CaveEdge, x: i32, y: i32) -> usize {
         let mut n = 0;
+        let (w, h) = (map.w() as i32, map.h() as i32);
 
         for i in 0..=2 {
             for j in 0..=2 {
-                let neighbour_x = x - 1 + i;
-                let neighbour_y = y - 1 + j;
+                let mut neighbour_x = x - 1 + i;
+                let mut neighbour_y = y - 1 + j;
 
                 if i == 1 && j == 1 {
                     continue;
                 }
 
-                // we consider edges as neighbor
-                if neighbour_x < 0
-                    || neighbour_y < 0
-                    || neighbour_x >= size[0] as i32
-                    || neighbour_y >= size[1] as i32
-                    || map[neighbour_x as usize + neighbour_y as usize * size[0]]
-                {
+                let is_outside =
+                    neighbour_x < 0 || neighbour_y < 0 || neighbour_x >= w || neighbour_y >= h;
+
+                if is_outside {
+                    match edge {
+                        CaveEdge::Floor => {
+                            n += 1;
+                            continue;
+                        }
+                        CaveEdge::Wall => continue,
+                        CaveEdge::Wrap => {
+                            neighbour_x = neighbour_x.rem_euclid(w);
+                            neighbour_y = neighbour_y.rem_euclid(h);
+                        }
+                    }
+                }
+
+                if map[[neighbour_x, neighbour_y]] {
                     n += 1;
                 }
             }
@@ -80,13 +152,13 @@ impl CaveMap {
 
 pub struct CaveGenAdvance {
     map: CaveMap,
-    rnd: ThreadRng,
+    params: CaveGenParams,
 }
 
 impl CaveGenAdvance {
-    pub fn new(size: [usize; 2], prob_init_floor: usize) -> Self {
-        let mut rnd = rand::thread_rng();
-
+    /// Fills the initial cells with the random number generator
+    pub fn new<R: Rng>(params: CaveGenParams, mut rnd: R) -> Self {
+        let size = params.size;
         let mut cells = Vec::with_capacity(size[0] * size[1]);
 
         // fill cells with initial distribution
@@ -94,56 +166,105 @@ impl CaveGenAdvance {
         for _y in 0..size[1] {
             for _x in 0..size[0] {
                 let r = dist.sample(&mut rnd);
-                cells.push(r < prob_init_floor);
+                cells.push(r < params.prob_init_floor);
             }
         }
 
+        let cells = Grid2d::from_vec(size, cells);
         let b = cells.clone();
         let bufs = DoubleSwap::new(cells, b);
 
         Self {
-            map: CaveMap { bufs, size },
-            rnd,
+            map: CaveMap { bufs },
+            params,
         }
     }
 
     fn advance(&mut self) {
         let bufs = &mut self.map.bufs;
+        let edge = self.params.edge;
 
-        for y in 0..self.map.size[1] {
-            for x in 0..self.map.size[0] {
-                let ix = x + y * self.map.size[0];
-
-                let nbs = CaveMap::count_neighbours(bufs.b(), self.map.size, x as i32, y as i32);
-                if bufs.b()[ix] {
-                    // kill?
-                    let death_limit = 2;
-                    bufs.a_mut()[ix] = nbs > death_limit;
-                } else {
-                    // birth?
-                    let birth_limit = 5;
-                    bufs.a_mut()[ix] = nbs > birth_limit;
-                }
+        for pos in bufs.a().bounds().iter_pos() {
+            // read from front buffer and write to back buffer
+            let nbs = CaveMap::count_neighbours(bufs.a(), edge, pos.x, pos.y);
+            if bufs.a()[pos] {
+                // kill?
+                bufs.b_mut()[pos] = nbs > self.params.death_limit;
+            } else {
+                // birth?
+                bufs.b_mut()[pos] = nbs > self.params.birth_limit;
             }
         }
 
+        // now the front buffer is the latest
         self.map.bufs.swap();
     }
 }
 
 // $ cargo test -- --nocapture --test-threads=1
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-
-//     #[test]
-//     fn test_gen() {
-//         let mut gen = CaveGenAdvance::new([32, 18], 50);
-//         gen.map.print().unwrap();
-//         println!("-------------");
-//         for _i in 0..20 {
-//             gen.advance();
-//         }
-//         gen.map.print().unwrap();
-//     }
-// }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::grid2d::Vec2i;
+
+    #[test]
+    fn test_reproducible() {
+        let params = CaveGenParams::new([32, 18], 1234);
+        assert_eq!(ca_gen_cave(&params), ca_gen_cave(&params));
+
+        let mut params = params;
+        params.edge = CaveEdge::Wrap;
+        assert_eq!(ca_gen_cave(&params), ca_gen_cave(&params));
+    }
+
+    fn is_border(cave: &Grid2d<bool>, pos: Vec2i) -> bool {
+        let [w, h] = [cave.w() as i32, cave.h() as i32];
+        pos.x == 0 || pos.y == 0 || pos.x == w - 1 || pos.y == h - 1
+    }
+
+    #[test]
+    fn test_edge() {
+        // every floor cell has 8 floor neighbours inside and dies with 5 or less
+        let mut params = CaveGenParams::new([16, 12], 0);
+        params.prob_init_floor = 100;
+        params.death_limit = 5;
+        params.n_steps = 1;
+
+        params.edge = CaveEdge::Wall;
+        let cave = ca_gen_cave(&params);
+        for (pos, is_floor) in cave.iter_pos() {
+            assert_eq!(*is_floor, !is_border(&cave, pos), "cell {:?}", pos);
+        }
+
+        params.edge = CaveEdge::Floor;
+        assert!(ca_gen_cave(&params).iter().all(|c| *c));
+
+        params.edge = CaveEdge::Wrap;
+        assert!(ca_gen_cave(&params).iter().all(|c| *c));
+    }
+
+    #[test]
+    fn test_fill_ratio() {
+        for seed in 0..8 {
+            let mut n_border = [0; 2];
+
+            for (i, edge) in [CaveEdge::Floor, CaveEdge::Wall].iter().enumerate() {
+                let mut params = CaveGenParams::new([48, 32], seed);
+                params.edge = *edge;
+                let cave = ca_gen_cave(&params);
+
+                let n_floors = cave.iter().filter(|c| **c).count();
+                let ratio = n_floors as f32 / cave.as_slice().len() as f32;
+                assert!(0.3 < ratio && ratio < 0.7, "{:?}: {}", edge, ratio);
+
+                n_border[i] = cave
+                    .iter_pos()
+                    .filter(|(pos, c)| **c && is_border(&cave, *pos))
+                    .count();
+            }
+
+            // walls outside of the map erode the border
+            assert!(n_border[1] < n_border[0], "{:?}", n_border);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+/*!
+Common output of the dungeon generators
+*/
+
+use std::collections::VecDeque;
+
+use {
+    rand::{rngs::StdRng, RngCore, SeedableRng},
+    serde::{Deserialize, Serialize},
+};
+
+use crate::rl::{
+    dun::{
+        bsp::{self, BspGenParams},
+        cave::{self, CaveGenParams},
+        region::{self, Connectivity, RegionMap},
+    },
+    grid2d::{Grid2d, Rect2i, Vec2i},
+};
+
+/// Logical kind of a generated cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CellKind {
+    Wall,
+    Floor,
+    Door,
+}
+
+impl CellKind {
+    pub fn is_walkable(&self) -> bool {
+        !matches!(self, Self::Wall)
+    }
+}
+
+/// Output of [`DungeonGen`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedLevel {
+    pub cells: Grid2d<CellKind>,
+    /// Rooms (empty for generators without the concept)
+    pub rooms: Vec<Rect2i>,
+    /// Candidates of the entrance (upstairs), sorted so that the first one is at an end of the
+    /// level
+    pub entrances: Vec<Vec2i>,
+    /// Candidates of the exit (downstairs), sorted so that the first one is the farthest from the
+    /// first entrance. Never shares cells with `entrances`
+    pub exits: Vec<Vec2i>,
+    /// Connected regions of walkable cells
+    pub regions: RegionMap,
+}
+
+impl GeneratedLevel {
+    /// Creates level from floor cells, labeling regions and finding entrance/exit candidates
+    ///
+    /// `candidates` are filtered to walkable cells in the largest region, then split into
+    /// entrances near one end of the level and exits far from it.
+    pub fn new(cells: Grid2d<CellKind>, rooms: Vec<Rect2i>, candidates: Vec<Vec2i>) -> Self {
+        let floor = self::walkable(&cells);
+        let regions = RegionMap::label(&floor, Connectivity::Eight);
+
+        let candidates = match regions.largest() {
+            Some(main) => candidates
+                .into_iter()
+                .filter(|pos| regions.region_at(*pos) == Some(main))
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        let (entrances, exits) = self::split_stairs(&floor, candidates);
+
+        Self {
+            cells,
+            rooms,
+            entrances,
+            exits,
+            regions,
+        }
+    }
+
+    /// Width, height
+    pub fn size(&self) -> [usize; 2] {
+        self.cells.size()
+    }
+
+    pub fn contains(&self, pos: impl Into<Vec2i>) -> bool {
+        self.cells.contains(pos)
+    }
+
+    /// Returns `Wall` if the position is outside of the level
+    pub fn cell(&self, pos: impl Into<Vec2i>) -> CellKind {
+        self.cells.get(pos).cloned().unwrap_or(CellKind::Wall)
+    }
+}
+
+/// Dungeon generator that can be swapped per floor
+pub trait DungeonGen {
+    /// Seed used by [`DungeonGen::gen`]
+    fn seed(&self) -> u64;
+
+    /// Generates a level with user-provided random number generator
+    fn gen_with(&self, rng: &mut dyn RngCore) -> GeneratedLevel;
+
+    /// Generates a level. The same generator always generates the same level.
+    fn gen(&self) -> GeneratedLevel {
+        let mut rng = StdRng::seed_from_u64(self.seed());
+        self.gen_with(&mut rng)
+    }
+}
+
+/// Cave with isolated regions handled by `connect`
+impl DungeonGen for CaveGenParams {
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn gen_with(&self, rng: &mut dyn RngCore) -> GeneratedLevel {
+        self::gen_cave(self, rng)
+    }
+}
+
+impl DungeonGen for BspGenParams {
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn gen_with(&self, mut rng: &mut dyn RngCore) -> GeneratedLevel {
+        let level = bsp::bsp_gen_with(self, &mut rng);
+
+        let mut cells = self::cell_kinds(&level.cells);
+        for door in &level.doors {
+            cells[*door] = CellKind::Door;
+        }
+
+        // room centers
+        let candidates = level
+            .rooms
+            .iter()
+            .map(|r| Vec2i::new(r.left() + r.w() as i32 / 2, r.up() + r.h() as i32 / 2))
+            .collect();
+
+        GeneratedLevel::new(cells, level.rooms, candidates)
+    }
+}
+
+/// Data-driven choice of [`DungeonGen`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DungeonGenParams {
+    Cave(CaveGenParams),
+    Bsp(BspGenParams),
+}
+
+impl DungeonGen for DungeonGenParams {
+    fn seed(&self) -> u64 {
+        match self {
+            Self::Cave(params) => params.seed,
+            Self::Bsp(params) => params.seed,
+        }
+    }
+
+    fn gen_with(&self, rng: &mut dyn RngCore) -> GeneratedLevel {
+        match self {
+            Self::Cave(params) => params.gen_with(rng),
+            Self::Bsp(params) => params.gen_with(rng),
+        }
+    }
+}
+
+fn gen_cave(params: &CaveGenParams, rng: &mut dyn RngCore) -> GeneratedLevel {
+    let mut floor = cave::ca_gen_cave_with(params, rng);
+    region::connect_regions(&mut floor, Connectivity::Eight, params.connect);
+
+    // open cells (surrounded by floors) are good for stairs
+    let candidates = floor
+        .iter_pos()
+        .map(|(pos, _is_floor)| pos)
+        .filter(|pos| {
+            Connectivity::Eight
+                .offsets()
+                .iter()
+                .chain(std::iter::once(&[0, 0]))
+                .all(|d| floor.get(*pos + Vec2i::from(d)) == Some(&true))
+        })
+        .collect();
+
+    GeneratedLevel::new(self::cell_kinds(&floor), Vec::new(), candidates)
+}
+
+fn cell_kinds(floor: &Grid2d<bool>) -> Grid2d<CellKind> {
+    Grid2d::from_fn(floor.size(), |pos| {
+        if floor[pos] {
+            CellKind::Floor
+        } else {
+            CellKind::Wall
+        }
+    })
+}
+
+fn walkable(cells: &Grid2d<CellKind>) -> Grid2d<bool> {
+    Grid2d::from_fn(cells.size(), |pos| cells[pos].is_walkable())
+}
+
+/// Splits stair candidates into entrances and exits
+///
+/// The first entrance is the candidate farthest from an arbitrary one, i.e. at an end of the level.
+/// Candidates closer to it than half the farthest distance are entrances (nearest first) and the
+/// others are exits (farthest first). If no candidate is far enough, the floor cell farthest from
+/// the first entrance becomes the exit.
+fn split_stairs(floor: &Grid2d<bool>, candidates: Vec<Vec2i>) -> (Vec<Vec2i>, Vec<Vec2i>) {
+    let first = match candidates.first() {
+        Some(pos) => *pos,
+        None => return (Vec::new(), Vec::new()),
+    };
+
+    let dists = self::distances(floor, first);
+    let dist = |dists: &Grid2d<Option<u32>>, pos: &Vec2i| dists.get(*pos).cloned().flatten();
+    let origin = candidates
+        .iter()
+        .filter(|pos| dist(&dists, pos).is_some())
+        .max_by_key(|pos| dist(&dists, pos))
+        .cloned()
+        .unwrap_or(first);
+
+    let dists = self::distances(floor, origin);
+    let mut candidates = candidates
+        .into_iter()
+        .filter_map(|pos| dist(&dists, &pos).map(|d| (pos, d)))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_pos, d)| *d);
+
+    let max = candidates.last().map(|(_pos, d)| *d).unwrap_or(0);
+    let (entrances, exits): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .partition(|(_pos, d)| *d * 2 < max.max(1));
+
+    let entrances = entrances.into_iter().map(|(pos, _d)| pos).collect();
+    let mut exits = exits
+        .into_iter()
+        .rev()
+        .map(|(pos, _d)| pos)
+        .collect::<Vec<_>>();
+
+    if exits.is_empty() {
+        // e.g. only one candidate. cells reachable from the origin are in the same region
+        let farthest = dists
+            .iter_pos()
+            .filter_map(|(pos, d)| d.filter(|d| *d > 0).map(|d| (pos, d)))
+            .max_by_key(|(_pos, d)| *d)
+            .map(|(pos, _d)| pos);
+        exits.extend(farthest);
+    }
+
+    (entrances, exits)
+}
+
+/// Walking distances (8 directions) from the origin
+fn distances(floor: &Grid2d<bool>, origin: Vec2i) -> Grid2d<Option<u32>> {
+    let mut dists = Grid2d::new(floor.size(), None);
+    let mut queue = VecDeque::new();
+
+    dists[origin] = Some(0);
+    queue.push_back(origin);
+
+    while let Some(pos) = queue.pop_front() {
+        let d = dists[pos].unwrap();
+        for offset in Connectivity::Eight.offsets() {
+            let nb = pos + Vec2i::from(offset);
+            if floor.get(nb) == Some(&true) && dists[nb].is_none() {
+                dists[nb] = Some(d + 1);
+                queue.push_back(nb);
+            }
+        }
+    }
+
+    dists
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rl::dun::ConnectPolicy;
+
+    use super::*;
+
+    /// Entrances and exits are distinct, and the first ones are far apart
+    fn assert_stairs(level: &GeneratedLevel) {
+        let entrance = level.entrances[0];
+        let exit = level.exits[0];
+
+        assert!(level.entrances.iter().all(|pos| !level.exits.contains(pos)));
+        for pos in level.entrances.iter().chain(&level.exits) {
+            assert!(level.cell(*pos).is_walkable());
+        }
+
+        let dists = self::distances(&self::walkable(&level.cells), entrance);
+        let dist = |pos: Vec2i| dists[pos].unwrap();
+
+        assert!(level.exits.iter().all(|pos| dist(*pos) <= dist(exit)));
+        let size = level.size();
+        assert!(dist(exit) * 2 >= size[0].max(size[1]) as u32);
+    }
+
+    /// Horizontal corridor of width 1
+    fn corridor(size: [usize; 2]) -> Grid2d<CellKind> {
+        Grid2d::from_fn(size, |pos| {
+            if pos.y == 1 && 0 < pos.x && pos.x < size[0] as i32 - 1 {
+                CellKind::Floor
+            } else {
+                CellKind::Wall
+            }
+        })
+    }
+
+    #[test]
+    fn test_corridor_stairs() {
+        let cells = self::corridor([12, 3]);
+        let candidates = (3..9).map(|x| Vec2i::new(x, 1)).collect();
+
+        // the entrance is at the end farthest from the first candidate
+        let level = GeneratedLevel::new(cells, Vec::new(), candidates);
+        assert_eq!(
+            level.entrances,
+            vec![Vec2i::new(8, 1), Vec2i::new(7, 1), Vec2i::new(6, 1)]
+        );
+        assert_eq!(
+            level.exits,
+            vec![Vec2i::new(3, 1), Vec2i::new(4, 1), Vec2i::new(5, 1)]
+        );
+    }
+
+    #[test]
+    fn test_single_candidate() {
+        // the exit falls back to the farthest floor
+        let cells = self::corridor([6, 3]);
+
+        let level = GeneratedLevel::new(cells, Vec::new(), vec![Vec2i::new(2, 1)]);
+        assert_eq!(level.entrances, vec![Vec2i::new(2, 1)]);
+        assert_eq!(level.exits, vec![Vec2i::new(4, 1)]);
+        assert_eq!(level.cell([-1, 1]), CellKind::Wall);
+        assert_eq!(level.cell([6, 1]), CellKind::Wall);
+
+        // no other floor
+        let mut cells = Grid2d::new([3, 3], CellKind::Wall);
+        cells[[1, 1]] = CellKind::Floor;
+
+        let level = GeneratedLevel::new(cells, Vec::new(), vec![Vec2i::new(1, 1)]);
+        assert_eq!(level.entrances, vec![Vec2i::new(1, 1)]);
+        assert!(level.exits.is_empty());
+    }
+
+    #[test]
+    fn test_cave_connect() {
+        let mut params = CaveGenParams::new([48, 32], 0);
+        for connect in [ConnectPolicy::FillSmaller, ConnectPolicy::Tunnel].iter() {
+            params.connect = *connect;
+            let level = params.gen();
+            assert_eq!(level.regions.n_regions(), 1, "{:?}", connect);
+        }
+
+        // tunnels keep more floors than filling
+        let mut n_floors = |connect| {
+            params.connect = connect;
+            let level = params.gen();
+            level.cells.iter().filter(|c| c.is_walkable()).count()
+        };
+        assert!(n_floors(ConnectPolicy::FillSmaller) <= n_floors(ConnectPolicy::Tunnel));
+    }
+
+    #[test]
+    fn test_cave_stairs() {
+        for seed in 0..8 {
+            let level = CaveGenParams::new([48, 32], seed).gen();
+            self::assert_stairs(&level);
+        }
+    }
+
+    #[test]
+    fn test_bsp_stairs() {
+        for seed in 0..8 {
+            let level = BspGenParams::new([48, 32], seed).gen();
+            self::assert_stairs(&level);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+/*!
+Connectivity of generated floors
+
+Generators output floor cells as `Grid2d<bool>` (floor if true), which often contain isolated
+pockets. [`RegionMap`] labels connected floors and [`connect_regions`] removes or
+joins those pockets.
+*/
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rl::grid2d::{Grid2d, Vec2i};
+
+/// Neighbours considered as connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Connectivity {
+    /// Orthogonal neighbours
+    Four,
+    /// Orthogonal and diagonal neighbours
+    Eight,
+}
+
+impl Connectivity {
+    pub fn offsets(&self) -> &'static [[i32; 2]] {
+        const FOUR: [[i32; 2]; 4] = [[0, -1], [1, 0], [0, 1], [-1, 0]];
+        const EIGHT: [[i32; 2]; 8] = [
+            [0, -1],
+            [1, -1],
+            [1, 0],
+            [1, 1],
+            [0, 1],
+            [-1, 1],
+            [-1, 0],
+            [-1, -1],
+        ];
+
+        match self {
+            Self::Four => &FOUR,
+            Self::Eight => &EIGHT,
+        }
+    }
+}
+
+/// How to handle isolated regions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConnectPolicy {
+    /// Fill every region but the largest one with walls
+    FillSmaller,
+    /// Carve shortest tunnels so that every region is joined to the largest one
+    Tunnel,
+}
+
+/// Region ids of floor cells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMap {
+    /// Region id for each cell, `None` if it's a wall
+    pub ids: Grid2d<Option<usize>>,
+    /// Number of cells for each region
+    pub areas: Vec<usize>,
+}
+
+impl RegionMap {
+    /// Labels connected floor cells with flood fill. Region ids are assigned in scan order.
+    pub fn label(cells: &Grid2d<bool>, conn: Connectivity) -> Self {
+        let mut ids = Grid2d::new(cells.size(), None);
+        let mut areas = Vec::new();
+        let mut queue = VecDeque::new();
+
+        for (start, is_floor) in cells.iter_pos() {
+            if !is_floor || ids[start].is_some() {
+                continue;
+            }
+
+            let id = areas.len();
+            let mut area = 0;
+
+            ids[start] = Some(id);
+            queue.push_back(start);
+
+            while let Some(pos) = queue.pop_front() {
+                area += 1;
+
+                for offset in conn.offsets() {
+                    let nb = pos + Vec2i::from(offset);
+                    if cells.get(nb) == Some(&true) && ids[nb].is_none() {
+                        ids[nb] = Some(id);
+                        queue.push_back(nb);
+                    }
+                }
+            }
+
+            areas.push(area);
+        }
+
+        Self { ids, areas }
+    }
+
+    /// Width, height
+    pub fn size(&self) -> [usize; 2] {
+        self.ids.size()
+    }
+
+    pub fn n_regions(&self) -> usize {
+        self.areas.len()
+    }
+
+    pub fn region_at(&self, pos: impl Into<Vec2i>) -> Option<usize> {
+        self.ids.get(pos).cloned().flatten()
+    }
+
+    /// Region with maximum area (the first one on tie)
+    pub fn largest(&self) -> Option<usize> {
+        let mut res: Option<usize> = None;
+        for (id, area) in self.areas.iter().enumerate() {
+            match res {
+                Some(r) if self.areas[r] >= *area => {}
+                _ => res = Some(id),
+            }
+        }
+        res
+    }
+
+    /// Cells in a region (e.g. candidates of spawn positions)
+    pub fn cells<'a>(&'a self, region: usize) -> impl Iterator<Item = Vec2i> + 'a {
+        self.ids
+            .iter_pos()
+            .filter(move |(_pos, id)| **id == Some(region))
+            .map(|(pos, _id)| pos)
+    }
+}
+
+/// Fills or joins isolated regions and returns the labels of the resulting floor
+pub fn connect_regions(
+    cells: &mut Grid2d<bool>,
+    conn: Connectivity,
+    policy: ConnectPolicy,
+) -> RegionMap {
+    let regions = RegionMap::label(cells, conn);
+
+    let main = match regions.largest() {
+        Some(r) => r,
+        None => return regions,
+    };
+
+    if regions.n_regions() == 1 {
+        return regions;
+    }
+
+    match policy {
+        ConnectPolicy::FillSmaller => {
+            for (pos, id) in regions.ids.iter_pos() {
+                if matches!(id, Some(r) if *r != main) {
+                    cells[pos] = false;
+                }
+            }
+        }
+        ConnectPolicy::Tunnel => {
+            // regions joined to the main region
+            let mut is_joined = vec![false; regions.n_regions()];
+            is_joined[main] = true;
+
+            for region in 0..regions.n_regions() {
+                if is_joined[region] {
+                    continue;
+                }
+
+                // tunnels may also join other regions on the way
+                for pos in self::carve_tunnel(cells, &regions, region, &is_joined) {
+                    if let Some(r) = regions.region_at(pos) {
+                        is_joined[r] = true;
+                    }
+                }
+                is_joined[region] = true;
+            }
+        }
+    }
+
+    RegionMap::label(cells, conn)
+}
+
+/// Carves the shortest orthogonal tunnel from `region` to any joined region. Returns the cells on
+/// the path.
+fn carve_tunnel(
+    cells: &mut Grid2d<bool>,
+    regions: &RegionMap,
+    region: usize,
+    is_joined: &[bool],
+) -> Vec<Vec2i> {
+    // multi-source BFS from the region
+    let mut prev = Grid2d::new(cells.size(), None);
+    let mut is_visited = Grid2d::new(cells.size(), false);
+    let mut queue = VecDeque::new();
+
+    for pos in regions.cells(region) {
+        is_visited[pos] = true;
+        queue.push_back(pos);
+    }
+
+    let goal = loop {
+        let pos = match queue.pop_front() {
+            Some(pos) => pos,
+            None => return Vec::new(),
+        };
+
+        if matches!(regions.region_at(pos), Some(r) if is_joined[r]) {
+            break pos;
+        }
+
+        for offset in Connectivity::Four.offsets() {
+            let nb = pos + Vec2i::from(offset);
+            if is_visited.get(nb) == Some(&false) {
+                is_visited[nb] = true;
+                prev[nb] = Some(pos);
+                queue.push_back(nb);
+            }
+        }
+    };
+
+    // trace back to the region, carving walls
+    let mut path = Vec::new();
+    let mut pos = goal;
+    while let Some(p) = prev[pos] {
+        cells[pos] = true;
+        path.push(pos);
+        pos = p;
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `.` is floor and `#` is wall
+    fn cells(src: &str) -> Grid2d<bool> {
+        let rows = src.lines().filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let size = [rows[0].len(), rows.len()];
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '.'))
+            .collect();
+        Grid2d::from_vec(size, cells)
+    }
+
+    const ROOMS: &str = "
+##########
+#...######
+#...###..#
+#...###..#
+##########
+";
+
+    #[test]
+    fn test_label() {
+        let cells = self::cells(ROOMS);
+
+        let regions = RegionMap::label(&cells, Connectivity::Four);
+        assert_eq!(regions.n_regions(), 2);
+        assert_eq!(regions.areas, vec![9, 4]);
+        assert_eq!(regions.largest(), Some(0));
+        assert_eq!(regions.region_at([2, 2]), Some(0));
+        assert_eq!(regions.region_at([7, 3]), Some(1));
+        assert_eq!(regions.region_at([0, 0]), None);
+        assert_eq!(regions.region_at([-1, 2]), None);
+        assert_eq!(regions.region_at([10, 2]), None);
+        assert_eq!(regions.cells(1).count(), 4);
+
+        // diagonal neighbours are connected only with `Eight`
+        let cells = self::cells(
+            "
+.#
+#.
+",
+        );
+        let n = |conn| RegionMap::label(&cells, conn).n_regions();
+        assert_eq!(n(Connectivity::Four), 2);
+        assert_eq!(n(Connectivity::Eight), 1);
+    }
+
+    #[test]
+    fn test_tunnel() {
+        let mut cells = self::cells(ROOMS);
+        let n_floors = cells.iter().filter(|c| **c).count();
+
+        let regions = connect_regions(&mut cells, Connectivity::Four, ConnectPolicy::Tunnel);
+        assert_eq!(regions.n_regions(), 1);
+
+        // both rooms are kept and joined with the tunnel
+        assert_eq!(regions.region_at([1, 1]), Some(0));
+        assert_eq!(regions.region_at([8, 3]), Some(0));
+        assert!(cells.iter().filter(|c| **c).count() > n_floors);
+    }
+
+    #[test]
+    fn test_fill_smaller() {
+        let mut cells = self::cells(ROOMS);
+
+        let regions = connect_regions(&mut cells, Connectivity::Four, ConnectPolicy::FillSmaller);
+        assert_eq!(regions.n_regions(), 1);
+        assert_eq!(regions.areas, vec![9]);
+
+        // the smaller room is filled
+        assert_eq!(regions.region_at([7, 2]), None);
+        assert!(!cells[[7, 2]]);
+        assert_eq!(cells.iter().filter(|c| **c).count(), 9);
+    }
+}
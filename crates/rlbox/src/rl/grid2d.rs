@@ -32,7 +32,7 @@ impl Rect2i {
     }
 
     pub fn size_i(&self) -> Vec2i {
-        Vec2i::new(self.pos.x as i32, self.pos.y as i32)
+        Vec2i::new(self.size.x as i32, self.size.y as i32)
     }
 
     pub fn w(&self) -> u32 {
@@ -55,22 +55,246 @@ impl Rect2i {
         self.pos
     }
 
-    // EXCLUSIVE or not
+    /// Exclusive
+    pub fn right(&self) -> i32 {
+        self.pos.x + self.size.x as i32
+    }
+
+    /// Exclusive
+    pub fn down(&self) -> i32 {
+        self.pos.y + self.size.y as i32
+    }
+
+    /// Exclusive
+    pub fn right_down(&self) -> Vec2i {
+        Vec2i::new(self.right(), self.down())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.x == 0 || self.size.y == 0
+    }
+
+    pub fn contains(&self, pos: impl Into<Vec2i>) -> bool {
+        let pos = pos.into();
+        self.left() <= pos.x && pos.x < self.right() && self.up() <= pos.y && pos.y < self.down()
+    }
+
+    /// Overlapping area. `None` if it's empty
+    pub fn intersect(&self, other: &Rect2i) -> Option<Rect2i> {
+        let left_up = Vec2i::new(
+            std::cmp::max(self.left(), other.left()),
+            std::cmp::max(self.up(), other.up()),
+        );
+        let right_down = Vec2i::new(
+            std::cmp::min(self.right(), other.right()),
+            std::cmp::min(self.down(), other.down()),
+        );
+
+        if left_up.x >= right_down.x || left_up.y >= right_down.y {
+            None
+        } else {
+            let size = right_down - left_up;
+            Some(Rect2i::new(left_up, [size.x as u32, size.y as u32]))
+        }
+    }
+
+    /// Positions in the rectangle (row-major)
+    pub fn iter_pos(&self) -> impl Iterator<Item = Vec2i> {
+        let (xs, ys) = (self.left()..self.right(), self.up()..self.down());
+        ys.flat_map(move |y| xs.clone().map(move |x| Vec2i::new(x, y)))
+    }
+}
+
+// --------------------------------------------------------------------------------
+// Container
+
+/// 2D array indexed with [`Vec2i`] (row-major)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Grid2d<T> {
+    /// Width, height
+    size: [usize; 2],
+    data: Vec<T>,
+}
+
+/// Constructors
+impl<T> Grid2d<T> {
+    /// Creates grid from data indexed as [x + y * width]
+    pub fn from_vec(size: [usize; 2], data: Vec<T>) -> Self {
+        assert_eq!(
+            data.len(),
+            size[0] * size[1],
+            "grid data doesn't match to the size"
+        );
+        Self { size, data }
+    }
+
+    pub fn from_fn(size: [usize; 2], mut f: impl FnMut(Vec2i) -> T) -> Self {
+        let mut data = Vec::with_capacity(size[0] * size[1]);
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                data.push(f(Vec2i::new(x as i32, y as i32)));
+            }
+        }
+        Self { size, data }
+    }
+
+    /// Data indexed as [x + y * width]
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+}
 
-    // pub fn right_up(&self) -> Vec2i {
-    //     Vec2i::new(self.pos.x + self.size.x as i32, self.pos.y)
-    // }
+impl<T: Clone> Grid2d<T> {
+    /// Creates grid filled with the value
+    pub fn new(size: [usize; 2], value: T) -> Self {
+        Self {
+            size,
+            data: vec![value; size[0] * size[1]],
+        }
+    }
+
+    pub fn fill(&mut self, value: T) {
+        for x in self.data.iter_mut() {
+            *x = value.clone();
+        }
+    }
+}
+
+/// Geometry
+impl<T> Grid2d<T> {
+    /// Width, height
+    pub fn size(&self) -> [usize; 2] {
+        self.size
+    }
+
+    pub fn w(&self) -> usize {
+        self.size[0]
+    }
+
+    pub fn h(&self) -> usize {
+        self.size[1]
+    }
+
+    pub fn bounds(&self) -> Rect2i {
+        Rect2i::new([0, 0], [self.size[0] as u32, self.size[1] as u32])
+    }
 
-    // pub fn left_down(&self) -> Vec2i {
-    //     Vec2i::new(self.pos.x, self.pos.y + self.size.y as i32)
-    // }
+    pub fn contains(&self, pos: impl Into<Vec2i>) -> bool {
+        self.ix(pos).is_some()
+    }
+
+    /// Index of the position in the underlying data. `None` if it's outside of the grid
+    pub fn ix(&self, pos: impl Into<Vec2i>) -> Option<usize> {
+        let pos = pos.into();
+        if pos.x < 0 || pos.y < 0 || pos.x >= self.size[0] as i32 || pos.y >= self.size[1] as i32 {
+            None
+        } else {
+            Some(pos.x as usize + pos.y as usize * self.size[0])
+        }
+    }
 
-    // pub fn right_down(&self) -> Vec2i {
-    //     Vec2i::new(
-    //         self.pos.x + self.size.x as i32,
-    //         self.pos.y + self.size.y as i32,
-    //     )
-    // }
+    /// Position of the index in the underlying data
+    pub fn pos(&self, ix: usize) -> Vec2i {
+        Vec2i::new((ix % self.size[0]) as i32, (ix / self.size[0]) as i32)
+    }
+}
+
+/// Access
+impl<T> Grid2d<T> {
+    pub fn get(&self, pos: impl Into<Vec2i>) -> Option<&T> {
+        let ix = self.ix(pos)?;
+        Some(&self.data[ix])
+    }
+
+    pub fn get_mut(&mut self, pos: impl Into<Vec2i>) -> Option<&mut T> {
+        let ix = self.ix(pos)?;
+        Some(&mut self.data[ix])
+    }
+
+    /// Returns false if it's outside of the grid
+    pub fn set(&mut self, pos: impl Into<Vec2i>, value: T) -> bool {
+        match self.get_mut(pos) {
+            Some(x) => {
+                *x = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Data indexed as [x + y * width]
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+/// Iterators
+impl<T> Grid2d<T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    /// Iterates through cells with their positions (row-major)
+    pub fn iter_pos(&self) -> impl Iterator<Item = (Vec2i, &T)> {
+        let w = self.size[0];
+        self.data
+            .iter()
+            .enumerate()
+            .map(move |(ix, x)| (Vec2i::new((ix % w) as i32, (ix / w) as i32), x))
+    }
+
+    pub fn row(&self, y: usize) -> Option<&[T]> {
+        if y >= self.size[1] {
+            None
+        } else {
+            let w = self.size[0];
+            Some(&self.data[y * w..(y + 1) * w])
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        // `chunks` panics with zero-sized chunks
+        let w = std::cmp::max(self.size[0], 1);
+        self.data.chunks(w)
+    }
+
+    /// Iterates through cells in the rectangle clipped with the grid bounds (row-major)
+    pub fn iter_rect<'a>(&'a self, rect: &Rect2i) -> impl Iterator<Item = (Vec2i, &'a T)> + 'a {
+        let rect = rect
+            .intersect(&self.bounds())
+            .unwrap_or_else(|| Rect2i::new([0, 0], [0, 0]));
+        rect.iter_pos().map(move |pos| (pos, &self[pos]))
+    }
+}
+
+impl<T, P: Into<Vec2i>> std::ops::Index<P> for Grid2d<T> {
+    type Output = T;
+
+    fn index(&self, pos: P) -> &T {
+        let pos = pos.into();
+        match self.ix(pos) {
+            Some(ix) => &self.data[ix],
+            None => panic!("position {:?} is out of the grid {:?}", pos, self.size),
+        }
+    }
+}
+
+impl<T, P: Into<Vec2i>> std::ops::IndexMut<P> for Grid2d<T> {
+    fn index_mut(&mut self, pos: P) -> &mut T {
+        let pos = pos.into();
+        match self.ix(pos) {
+            Some(ix) => &mut self.data[ix],
+            None => panic!("position {:?} is out of the grid {:?}", pos, self.size),
+        }
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, Inspect)]
@@ -235,3 +459,108 @@ impl From<&[u32; 2]> for Vec2u {
         Self::new(xs[0], xs[1])
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rect() {
+        let rect = Rect2i::new([-1, 2], [3, 2]);
+        assert_eq!((rect.right(), rect.down()), (2, 4));
+        assert_eq!(rect.right_down(), Vec2i::new(2, 4));
+
+        // right and down are exclusive
+        assert!(rect.contains([-1, 2]));
+        assert!(rect.contains([1, 3]));
+        assert!(!rect.contains([2, 3]));
+        assert!(!rect.contains([1, 4]));
+        assert!(!rect.contains([-2, 2]));
+
+        let empty = Rect2i::new([0, 0], [0, 3]);
+        assert!(empty.is_empty());
+        assert!(!empty.contains([0, 0]));
+        assert_eq!(empty.iter_pos().count(), 0);
+
+        let xs = Rect2i::new([-1, -1], [2, 2]).iter_pos().collect::<Vec<_>>();
+        assert_eq!(
+            xs,
+            vec![
+                Vec2i::new(-1, -1),
+                Vec2i::new(0, -1),
+                Vec2i::new(-1, 0),
+                Vec2i::new(0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_intersect() {
+        let a = Rect2i::new([0, 0], [4, 4]);
+
+        let b = Rect2i::new([-2, 2], [4, 4]);
+        assert_eq!(a.intersect(&b), Some(Rect2i::new([0, 2], [2, 2])));
+        assert_eq!(b.intersect(&a), a.intersect(&b));
+
+        // touching edges don't overlap
+        assert_eq!(a.intersect(&Rect2i::new([4, 0], [2, 2])), None);
+        assert_eq!(a.intersect(&Rect2i::new([0, -2], [2, 2])), None);
+        // disjoint
+        assert_eq!(a.intersect(&Rect2i::new([-5, -5], [2, 2])), None);
+        // empty
+        assert_eq!(a.intersect(&Rect2i::new([1, 1], [0, 2])), None);
+    }
+
+    #[test]
+    fn test_grid_access() {
+        let mut grid = Grid2d::from_fn([3, 2], |pos| pos.x + pos.y * 10);
+        assert_eq!(grid.as_slice(), &[0, 1, 2, 10, 11, 12]);
+
+        for ix in 0..6 {
+            assert_eq!(grid.ix(grid.pos(ix)), Some(ix));
+        }
+
+        assert_eq!(grid.get([2, 1]), Some(&12));
+        assert_eq!(grid[[1, 1]], 11);
+
+        for pos in [[-1, 0], [0, -1], [3, 0], [0, 2], [-1, -1], [3, 2]].iter() {
+            assert!(!grid.contains(*pos));
+            assert_eq!(grid.ix(*pos), None);
+            assert_eq!(grid.get(*pos), None);
+            assert!(!grid.set(*pos, 99));
+        }
+        // x out of bounds doesn't wrap into the next row
+        assert_eq!(grid.get([3, 0]), None);
+        assert_eq!(grid.as_slice(), &[0, 1, 2, 10, 11, 12]);
+
+        assert!(grid.set([0, 1], -1));
+        assert_eq!(grid[[0, 1]], -1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_grid_index_out_of_bounds() {
+        let grid = Grid2d::new([2, 2], 0);
+        let _ = grid[[-1, 0]];
+    }
+
+    #[test]
+    fn test_grid_iter() {
+        let grid = Grid2d::from_fn([3, 2], |pos| pos.x + pos.y * 10);
+
+        let xs = grid.iter_pos().map(|(p, x)| (p, *x)).collect::<Vec<_>>();
+        assert_eq!(xs[4], (Vec2i::new(1, 1), 11));
+
+        assert_eq!(grid.row(1), Some(&[10, 11, 12][..]));
+        assert_eq!(grid.row(2), None);
+        assert_eq!(grid.rows().count(), 2);
+
+        // clipped with the grid bounds
+        let rect = Rect2i::new([-1, 1], [3, 5]);
+        let xs = grid.iter_rect(&rect).map(|(_, x)| *x).collect::<Vec<_>>();
+        assert_eq!(xs, vec![10, 11]);
+
+        let outside = Rect2i::new([3, 0], [2, 2]);
+        assert_eq!(grid.iter_rect(&outside).count(), 0);
+    }
+}
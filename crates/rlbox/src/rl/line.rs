@@ -0,0 +1,303 @@
+/*!
+Lines on grid and projectile paths
+*/
+
+use std::collections::VecDeque;
+
+use crate::rl::{grid2d::Vec2i, shadow::OpacityMap};
+
+/// Bresenham line from `from` to `to` (both inclusive)
+///
+/// Ties are rounded up, the same as the rows of the symmetric shadowcasting are scanned, so that
+/// lines to the cells in view are mostly unobstructed.
+pub fn bresenham(from: impl Into<Vec2i>, to: impl Into<Vec2i>) -> Bresenham {
+    Bresenham::new(from.into(), to.into())
+}
+
+/// Every cell the segment between the centers of `from` and `to` touches (both inclusive). Both
+/// cells are yielded when it passes through a corner
+pub fn supercover(from: impl Into<Vec2i>, to: impl Into<Vec2i>) -> Supercover {
+    Supercover::new(from.into(), to.into())
+}
+
+/// Iterator of [`bresenham`] line
+#[derive(Debug, Clone)]
+pub struct Bresenham {
+    from: Vec2i,
+    delta: Vec2i,
+    /// Number of steps
+    n: i32,
+    /// Next step
+    i: i32,
+}
+
+impl Bresenham {
+    fn new(from: Vec2i, to: Vec2i) -> Self {
+        let delta = to - from;
+        Self {
+            from,
+            delta,
+            n: std::cmp::max(delta.x.abs(), delta.y.abs()),
+            i: 0,
+        }
+    }
+}
+
+impl Iterator for Bresenham {
+    type Item = Vec2i;
+
+    fn next(&mut self) -> Option<Vec2i> {
+        if self.i > self.n {
+            return None;
+        }
+
+        let i = self.i;
+        self.i += 1;
+
+        if self.n == 0 {
+            return Some(self.from);
+        }
+
+        // floor(i * d / n + 0.5)
+        let round = |d: i32| (2 * i * d + self.n).div_euclid(2 * self.n);
+        Some(self.from + Vec2i::new(round(self.delta.x), round(self.delta.y)))
+    }
+}
+
+/// Iterator of [`supercover`] line
+#[derive(Debug, Clone)]
+pub struct Supercover {
+    pos: Vec2i,
+    to: Vec2i,
+    step: Vec2i,
+    /// 2 * |dx|, 2 * |dy|
+    d2: [i32; 2],
+    error: i32,
+    /// Cells to be yielded before stepping forward
+    queue: VecDeque<Vec2i>,
+    is_done: bool,
+}
+
+impl Supercover {
+    fn new(from: Vec2i, to: Vec2i) -> Self {
+        let d = to - from;
+        Self {
+            pos: from,
+            to,
+            step: Vec2i::new(d.x.signum(), d.y.signum()),
+            d2: [d.x.abs() * 2, d.y.abs() * 2],
+            error: d.x.abs() - d.y.abs(),
+            queue: vec![from].into(),
+            is_done: false,
+        }
+    }
+}
+
+impl Iterator for Supercover {
+    type Item = Vec2i;
+
+    fn next(&mut self) -> Option<Vec2i> {
+        if let Some(pos) = self.queue.pop_front() {
+            return Some(pos);
+        }
+
+        if self.is_done || self.pos == self.to {
+            self.is_done = true;
+            return None;
+        }
+
+        if self.error > 0 {
+            self.pos.x += self.step.x;
+            self.error -= self.d2[1];
+        } else if self.error < 0 {
+            self.pos.y += self.step.y;
+            self.error += self.d2[0];
+        } else {
+            // passing through a corner
+            self.queue.push_back(self.pos + Vec2i::new(self.step.x, 0));
+            self.queue.push_back(self.pos + Vec2i::new(0, self.step.y));
+            self.pos += self.step;
+            self.error += self.d2[0] - self.d2[1];
+        }
+
+        self.queue.push_back(self.pos);
+        self.queue.pop_front()
+    }
+}
+
+/// What stopped a projectile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProjectileHit {
+    /// Opaque cell or outside of the map
+    Block(Vec2i),
+    Actor(Vec2i),
+}
+
+/// Output of [`trace_projectile`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectilePath {
+    /// Cells the projectile passed through (excluding the start and the hit cell)
+    pub cells: Vec<Vec2i>,
+    /// `None` if it reached the target (or the maximum range) without hitting anything
+    pub hit: Option<ProjectileHit>,
+}
+
+impl ProjectilePath {
+    /// The last cell of the flight (e.g. where a thrown item drops)
+    pub fn last_cell(&self) -> Option<Vec2i> {
+        self.cells.last().cloned()
+    }
+}
+
+/// Traces a [`bresenham`] line to `to` until it's blocked or reaches `max_range` (in king's moves)
+pub fn trace_projectile(
+    from: impl Into<Vec2i>,
+    to: impl Into<Vec2i>,
+    opa: &impl OpacityMap,
+    max_range: u32,
+) -> ProjectilePath {
+    self::trace_projectile_with(from, to, opa, max_range, |_pos| false)
+}
+
+/// [`trace_projectile`] that is also stopped by actors
+pub fn trace_projectile_with(
+    from: impl Into<Vec2i>,
+    to: impl Into<Vec2i>,
+    opa: &impl OpacityMap,
+    max_range: u32,
+    is_occupied: impl Fn(Vec2i) -> bool,
+) -> ProjectilePath {
+    let from = from.into();
+    let mut path = ProjectilePath {
+        cells: Vec::new(),
+        hit: None,
+    };
+
+    for pos in self::bresenham(from, to).skip(1) {
+        if (pos - from).len_king() > max_range {
+            break;
+        }
+
+        if !opa.contains(pos) || opa.is_opaque(pos) {
+            path.hit = Some(ProjectileHit::Block(pos));
+            break;
+        }
+
+        if is_occupied(pos) {
+            path.hit = Some(ProjectileHit::Actor(pos));
+            break;
+        }
+
+        path.cells.push(pos);
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn targets() -> impl Iterator<Item = Vec2i> {
+        (-5..=5).flat_map(|y| (-5..=5).map(move |x| Vec2i::new(x, y)))
+    }
+
+    #[test]
+    fn test_bresenham() {
+        let from = Vec2i::new(1, 2);
+
+        for to in self::targets().map(|d| from + d) {
+            let line = bresenham(from, to).collect::<Vec<_>>();
+            assert_eq!(line.first(), Some(&from));
+            assert_eq!(line.last(), Some(&to));
+            assert_eq!(line.len() as u32, (to - from).len_king() + 1);
+            assert!(line.windows(2).all(|w| (w[1] - w[0]).len_king() == 1));
+
+            // the same cells in both directions
+            let mut rev = bresenham(to, from).collect::<Vec<_>>();
+            rev.reverse();
+            assert_eq!(line, rev, "{:?} -> {:?}", from, to);
+        }
+
+        let line = bresenham([0, 0], [3, 3]).collect::<Vec<_>>();
+        assert_eq!(
+            line,
+            vec![
+                Vec2i::new(0, 0),
+                Vec2i::new(1, 1),
+                Vec2i::new(2, 2),
+                Vec2i::new(3, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_supercover() {
+        let from = Vec2i::new(1, 2);
+
+        for to in self::targets().map(|d| from + d) {
+            let line = supercover(from, to).collect::<Vec<_>>();
+            assert_eq!(line.first(), Some(&from));
+            assert_eq!(line.last(), Some(&to));
+
+            // covers the Bresenham line
+            assert!(bresenham(from, to).all(|pos| line.contains(&pos)));
+
+            // the same cells in both directions
+            let mut a = line.clone();
+            let mut b = supercover(to, from).collect::<Vec<_>>();
+            a.sort_by_key(|p| (p.x, p.y));
+            b.sort_by_key(|p| (p.x, p.y));
+            assert_eq!(a, b, "{:?} -> {:?}", from, to);
+        }
+
+        // both cells at diagonal corners
+        let line = supercover([0, 0], [2, 2]).collect::<Vec<_>>();
+        assert_eq!(
+            line,
+            vec![
+                Vec2i::new(0, 0),
+                Vec2i::new(1, 0),
+                Vec2i::new(0, 1),
+                Vec2i::new(1, 1),
+                Vec2i::new(2, 1),
+                Vec2i::new(1, 2),
+                Vec2i::new(2, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_projectile() {
+        let map = crate::rl::ascii::parse(
+            "
+.......
+.....#.
+.......
+",
+        )
+        .unwrap()
+        .map;
+
+        // reaches the target
+        let path = trace_projectile([0, 0], [6, 0], &map, 10);
+        assert_eq!(path.hit, None);
+        assert_eq!(path.last_cell(), Some(Vec2i::new(6, 0)));
+        assert_eq!(path.cells.len(), 6);
+
+        // blocked by a wall
+        let path = trace_projectile([0, 1], [6, 1], &map, 10);
+        assert_eq!(path.hit, Some(ProjectileHit::Block(Vec2i::new(5, 1))));
+        assert_eq!(path.last_cell(), Some(Vec2i::new(4, 1)));
+
+        // maximum range
+        let path = trace_projectile([0, 2], [6, 2], &map, 3);
+        assert_eq!(path.hit, None);
+        assert_eq!(path.last_cell(), Some(Vec2i::new(3, 2)));
+
+        // blocked by an actor
+        let path = trace_projectile_with([0, 2], [6, 2], &map, 10, |pos| pos == Vec2i::new(2, 2));
+        assert_eq!(path.hit, Some(ProjectileHit::Actor(Vec2i::new(2, 2))));
+        assert_eq!(path.cells, vec![Vec2i::new(1, 2)]);
+    }
+}
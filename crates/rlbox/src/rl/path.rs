@@ -0,0 +1,317 @@
+/*!
+Pathfinding (A*)
+*/
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::rl::{
+    grid2d::{Dir8, Vec2i},
+    rlmap::RlMap,
+};
+
+/// Walkability of cells, which is queried by the pathfinder
+pub trait WalkMap {
+    fn is_blocked(&self, pos: Vec2i) -> bool;
+    fn contains(&self, pos: Vec2i) -> bool;
+
+    /// Cost of entering the cell. Must be greater than or equal to `1`
+    fn cost(&self, _pos: Vec2i) -> u32 {
+        1
+    }
+}
+
+impl WalkMap for RlMap {
+    fn is_blocked(&self, pos: Vec2i) -> bool {
+        !self.is_walkable(pos)
+    }
+
+    fn contains(&self, pos: Vec2i) -> bool {
+        <Self>::contains(self, pos)
+    }
+
+    fn cost(&self, pos: Vec2i) -> u32 {
+        self.terrain(pos).map_or(1, |t| t.move_cost.max(1))
+    }
+}
+
+/// [`WalkMap`] with cells occupied by actors
+pub struct Occupied<'a, M: ?Sized, F> {
+    pub map: &'a M,
+    /// Returns true if there's an actor at the position
+    pub is_occupied: F,
+}
+
+impl<'a, M: WalkMap + ?Sized, F: Fn(Vec2i) -> bool> Occupied<'a, M, F> {
+    pub fn new(map: &'a M, is_occupied: F) -> Self {
+        Self { map, is_occupied }
+    }
+}
+
+impl<'a, M: WalkMap + ?Sized, F: Fn(Vec2i) -> bool> WalkMap for Occupied<'a, M, F> {
+    fn is_blocked(&self, pos: Vec2i) -> bool {
+        self.map.is_blocked(pos) || (self.is_occupied)(pos)
+    }
+
+    fn contains(&self, pos: Vec2i) -> bool {
+        self.map.contains(pos)
+    }
+
+    fn cost(&self, pos: Vec2i) -> u32 {
+        self.map.cost(pos)
+    }
+}
+
+/// When diagonal moves are allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiagonalRule {
+    /// Only orthogonal moves
+    Never,
+    /// Diagonal moves are always allowed, even between two blocked cells
+    Always,
+    /// Diagonal moves are allowed if at least one of the two orthogonal neighbours is free
+    IfOneFree,
+    /// Diagonal moves are allowed only if both orthogonal neighbours are free (no corner cutting)
+    IfBothFree,
+}
+
+/// Parameters of [`find_path`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PathParams {
+    pub diagonal: DiagonalRule,
+    /// Cost multiplier of orthogonal moves
+    pub orth_cost: u32,
+    /// Cost multiplier of diagonal moves
+    pub diag_cost: u32,
+    /// Gives up paths that cost more than this
+    pub max_cost: Option<u32>,
+}
+
+impl Default for PathParams {
+    /// Roguelike moves: diagonal moves cost the same as orthogonal ones, without corner cutting
+    fn default() -> Self {
+        Self {
+            diagonal: DiagonalRule::IfBothFree,
+            orth_cost: 1,
+            diag_cost: 1,
+            max_cost: None,
+        }
+    }
+}
+
+impl PathParams {
+    pub(crate) fn can_move(&self, map: &impl WalkMap, from: Vec2i, dir: Dir8) -> bool {
+        let [dx, dy] = dir.signs_i32();
+        if dx == 0 || dy == 0 {
+            return true;
+        }
+
+        let (a, b) = (
+            map.is_blocked(from + Vec2i::new(dx, 0)),
+            map.is_blocked(from + Vec2i::new(0, dy)),
+        );
+
+        match self.diagonal {
+            DiagonalRule::Never => false,
+            DiagonalRule::Always => true,
+            DiagonalRule::IfOneFree => !(a && b),
+            DiagonalRule::IfBothFree => !(a || b),
+        }
+    }
+
+    pub(crate) fn step_cost(&self, dir: Dir8) -> u32 {
+        let [dx, dy] = dir.signs_i32();
+        if dx == 0 || dy == 0 {
+            self.orth_cost
+        } else {
+            self.diag_cost
+        }
+    }
+
+    /// Lower bound of the cost from `from` to `to` (cell costs are at least `1`)
+    fn heuristic(&self, from: Vec2i, to: Vec2i) -> u32 {
+        let d = to - from;
+        let (dx, dy) = (d.x.abs() as u32, d.y.abs() as u32);
+
+        if self.diagonal == DiagonalRule::Never {
+            return (dx + dy) * self.orth_cost;
+        }
+
+        let (min, max) = (dx.min(dy), dx.max(dy));
+        min * self.diag_cost.min(self.orth_cost * 2) + (max - min) * self.orth_cost
+    }
+}
+
+/// Finds the cheapest path with A*. Returns `None` if the goal is unreachable
+///
+/// The goal cell is considered as walkable even if it's blocked, so that actors can find paths to
+/// other actors. Drop the last step to stop next to the goal.
+pub fn find_path(
+    map: &impl WalkMap,
+    from: impl Into<Vec2i>,
+    to: impl Into<Vec2i>,
+    params: &PathParams,
+) -> Option<Vec<Dir8>> {
+    let (from, to) = (from.into(), to.into());
+
+    if !map.contains(to) {
+        return None;
+    }
+
+    // position -> (cost from the start, direction from the previous cell)
+    let mut nodes = HashMap::<Vec2i, (u32, Option<Dir8>)>::new();
+    // (estimated total cost, reversed cost from the start): prefer nodes closer to the goal on tie
+    let mut open = BinaryHeap::new();
+
+    nodes.insert(from, (0, None));
+    open.push(Reverse((
+        params.heuristic(from, to),
+        Reverse(0),
+        [from.x, from.y],
+    )));
+
+    while let Some(Reverse((_f, Reverse(g), pos))) = open.pop() {
+        let pos = Vec2i::from(pos);
+
+        if pos == to {
+            return Some(self::trace_back(&nodes, to));
+        }
+
+        // skip outdated entries
+        if g > nodes[&pos].0 {
+            continue;
+        }
+
+        for dir in Dir8::CLOCKWISE.iter().cloned() {
+            let nb = pos + Vec2i::from(dir);
+            if !map.contains(nb) || (nb != to && map.is_blocked(nb)) {
+                continue;
+            }
+
+            if !params.can_move(map, pos, dir) {
+                continue;
+            }
+
+            let nb_g = g + params.step_cost(dir) * map.cost(nb);
+            if matches!(params.max_cost, Some(max) if nb_g > max) {
+                continue;
+            }
+
+            if matches!(nodes.get(&nb), Some((old, _)) if *old <= nb_g) {
+                continue;
+            }
+
+            nodes.insert(nb, (nb_g, Some(dir)));
+            let f = nb_g + params.heuristic(nb, to);
+            open.push(Reverse((f, Reverse(nb_g), [nb.x, nb.y])));
+        }
+    }
+
+    None
+}
+
+fn trace_back(nodes: &HashMap<Vec2i, (u32, Option<Dir8>)>, goal: Vec2i) -> Vec<Dir8> {
+    let mut path = Vec::new();
+
+    let mut pos = goal;
+    while let Some(dir) = nodes[&pos].1 {
+        path.push(dir);
+        pos = pos - Vec2i::from(dir);
+    }
+
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::rlmap::{Liquid, Terrain};
+
+    /// `#` is wall (see [`crate::rl::ascii`])
+    fn map(rows: &[&str]) -> RlMap {
+        crate::rl::ascii::parse(&rows.join("\n")).unwrap().map
+    }
+
+    fn walk(from: Vec2i, path: &[Dir8]) -> Vec2i {
+        path.iter().fold(from, |pos, dir| pos + Vec2i::from(*dir))
+    }
+
+    #[test]
+    fn test_find_path() {
+        let map = self::map(&[
+            "......", //
+            ".####.", //
+            "....#.", //
+            "###.#.", //
+            "......", //
+        ]);
+
+        let (from, to) = (Vec2i::new(0, 2), Vec2i::new(5, 4));
+
+        // no corner cutting
+        let path = find_path(&map, from, to, &PathParams::default()).unwrap();
+        assert_eq!(walk(from, &path), to);
+        assert_eq!(path.len(), 7);
+
+        let params = PathParams {
+            diagonal: DiagonalRule::IfOneFree,
+            ..Default::default()
+        };
+        let path = find_path(&map, from, to, &params).unwrap();
+        assert_eq!(walk(from, &path), to);
+        assert_eq!(path.len(), 5);
+
+        // blocked by an actor
+        let occupied = Occupied::new(&map, |pos| pos == Vec2i::new(3, 3));
+        let path = find_path(&occupied, from, to, &PathParams::default()).unwrap();
+        assert_eq!(walk(from, &path), to);
+        assert_eq!(path.len(), 11);
+
+        let params = PathParams {
+            max_cost: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(find_path(&occupied, from, to, &params), None);
+    }
+
+    #[test]
+    fn test_terrain() {
+        let mut map = self::map(&[
+            ".....", //
+            ".....", //
+            ".....", //
+        ]);
+
+        let mud = map.add_terrain(Terrain {
+            move_cost: 4,
+            ..Default::default()
+        });
+        let deep = map.add_terrain(Terrain {
+            liquid: Some(Liquid::Deep),
+            ..Default::default()
+        });
+        map.set_terrain([2, 0], deep);
+        map.set_terrain([2, 1], mud);
+        map.set_terrain([2, 2], mud);
+
+        let (from, to) = (Vec2i::new(0, 1), Vec2i::new(4, 1));
+        let path = find_path(&map, from, to, &PathParams::default()).unwrap();
+        assert_eq!(walk(from, &path), to);
+
+        // walk through the mud only once and never enter the deep water
+        let cells = path
+            .iter()
+            .scan(from, |pos, dir| {
+                *pos += Vec2i::from(*dir);
+                Some(*pos)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cells.iter().filter(|p| p.x == 2).count(), 1);
+        assert!(!cells.contains(&Vec2i::new(2, 0)));
+    }
+}
@@ -1,56 +1,329 @@
 /*!
-Roguelike map with Tiled backend
+Roguelike map
 
-TODO: tiled-free implementation
+It can be loaded from a Tiled map or created from generated/programmatic cell data.
 */
 
-use crate::rl::{grid2d::Vec2i, shadow::OpacityMap};
+use std::collections::HashMap;
+
+use crate::rl::{
+    dun::{CellKind, GeneratedLevel},
+    grid2d::{Grid2d, Rect2i, Vec2i},
+    shadow::OpacityMap,
+};
+
+/// Index of [`Terrain`] in the terrain table of [`RlMap`]
+pub type TerrainId = u16;
+
+/// Liquid on a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Liquid {
+    /// Walkable
+    Shallow,
+    /// Not walkable
+    Deep,
+}
+
+/// Stairs on a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StairsKind {
+    Up,
+    Down,
+}
+
+/// State of a door cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DoorState {
+    Open,
+    Closed,
+    /// Closed and can't be opened
+    Locked,
+}
+
+impl DoorState {
+    pub fn is_open(self) -> bool {
+        self == Self::Open
+    }
+}
+
+/// Attributes of a cell type
+///
+/// Loaded from Tiled tile properties: `move-cost` (int), `liquid` (`"shallow"` or `"deep"`),
+/// `is-door`, `stairs` (`"up"` or `"down"`), `hazard-damage` (int) and `light-block` (float).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Terrain {
+    /// Cost of entering the cell (at least `1`)
+    pub move_cost: u32,
+    pub liquid: Option<Liquid>,
+    pub is_door: bool,
+    pub stairs: Option<StairsKind>,
+    /// Damage on entering the cell
+    pub hazard_damage: u32,
+    /// Fraction of light blocked by the cell in range [0.0, 1.0]. `1.0` blocks view
+    pub light_block: f32,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            move_cost: 1,
+            liquid: None,
+            is_door: false,
+            stairs: None,
+            hazard_damage: 0,
+            light_block: 0.0,
+        }
+    }
+}
+
+impl Terrain {
+    /// Terrain of a door cell
+    pub fn door() -> Self {
+        Self {
+            is_door: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_walkable(&self) -> bool {
+        self.liquid != Some(Liquid::Deep)
+    }
+
+    /// Reads typed attributes from Tiled properties. Unknown keys are ignored
+    pub fn from_tiled_props(props: &tiled::Properties) -> Self {
+        use tiled::PropertyValue as P;
+
+        let mut terrain = Self::default();
+
+        for (key, value) in props {
+            match (key.as_str(), value) {
+                ("move-cost", P::IntValue(x)) => terrain.move_cost = (*x).max(1) as u32,
+                ("liquid", P::StringValue(s)) => {
+                    terrain.liquid = match s.as_str() {
+                        "shallow" => Some(Liquid::Shallow),
+                        "deep" => Some(Liquid::Deep),
+                        _ => {
+                            log::warn!("unknown liquid: {}", s);
+                            None
+                        }
+                    }
+                }
+                ("is-door", P::BoolValue(b)) => terrain.is_door = *b,
+                ("stairs", P::StringValue(s)) => {
+                    terrain.stairs = match s.as_str() {
+                        "up" => Some(StairsKind::Up),
+                        "down" => Some(StairsKind::Down),
+                        _ => {
+                            log::warn!("unknown stairs: {}", s);
+                            None
+                        }
+                    }
+                }
+                ("hazard-damage", P::IntValue(x)) => terrain.hazard_damage = (*x).max(0) as u32,
+                _ => {}
+            }
+        }
+
+        // `light-block` overrides `is-view-block`
+        terrain.light_block = match props.get("light-block") {
+            Some(P::FloatValue(x)) => x.max(0.0).min(1.0),
+            _ if self::is_tiled_flag(props, "is-view-block") => 1.0,
+            _ => 0.0,
+        };
+
+        terrain
+    }
+}
 
 /// Roguelike map data
 #[derive(Debug)]
 pub struct RlMap {
-    pub size: [usize; 2],
     /// True if it's physical block
-    pub body_blocks: Vec<bool>,
+    pub body_blocks: Grid2d<bool>,
     /// True if it's view block
-    pub view_blocks: Vec<bool>,
+    pub view_blocks: Grid2d<bool>,
+    /// Terrain table. The first item is the default terrain
+    pub terrains: Vec<Terrain>,
+    /// Indices into the terrain table
+    pub terrain_ids: Grid2d<TerrainId>,
+    /// States of door cells
+    pub doors: Grid2d<Option<DoorState>>,
 }
 
+/// Tiled-free constructors
 impl RlMap {
+    /// Creates a map without any block
+    pub fn new(size: [usize; 2]) -> Self {
+        Self {
+            body_blocks: Grid2d::new(size, false),
+            view_blocks: Grid2d::new(size, false),
+            terrains: vec![Terrain::default()],
+            terrain_ids: Grid2d::new(size, 0),
+            doors: Grid2d::new(size, None),
+        }
+    }
+
+    /// Creates a map from blocks indexed as [x + y * width]
+    pub fn from_blocks(size: [usize; 2], body_blocks: Vec<bool>, view_blocks: Vec<bool>) -> Self {
+        Self {
+            body_blocks: Grid2d::from_vec(size, body_blocks),
+            view_blocks: Grid2d::from_vec(size, view_blocks),
+            terrains: vec![Terrain::default()],
+            terrain_ids: Grid2d::new(size, 0),
+            doors: Grid2d::new(size, None),
+        }
+    }
+
+    /// Creates a map from floor cells indexed as [x + y * width]. Walls block both body and view.
+    pub fn from_floor(size: [usize; 2], floor: &[bool]) -> Self {
+        let blocks = floor.iter().map(|b| !b).collect::<Vec<_>>();
+        Self::from_blocks(size, blocks.clone(), blocks)
+    }
+
+    /// Creates a map from cell data returning `[is_body_block, is_view_block]` for each position
+    pub fn from_fn(size: [usize; 2], mut f: impl FnMut(Vec2i) -> [bool; 2]) -> Self {
+        let mut map = Self::new(size);
+
+        for pos in map.body_blocks.bounds().iter_pos() {
+            let [body, view] = f(pos);
+            map.set_blocks(pos, body, view);
+        }
+
+        map
+    }
+
+    /// Creates a map from the output of dungeon generators. Doors are open
+    pub fn from_level(level: &GeneratedLevel) -> Self {
+        let mut map = Self::from_fn(level.size(), |pos| match level.cell(pos) {
+            CellKind::Wall => [true, true],
+            CellKind::Floor | CellKind::Door => [false, false],
+        });
+
+        let door = map.add_terrain(Terrain::door());
+        for pos in map.body_blocks.bounds().iter_pos() {
+            if level.cell(pos) == CellKind::Door {
+                map.set_terrain(pos, door);
+                map.set_door(pos, DoorState::Open);
+            }
+        }
+
+        map
+    }
+}
+
+impl RlMap {
+    /// Width, height
+    pub fn size(&self) -> [usize; 2] {
+        self.body_blocks.size()
+    }
+
     pub fn contains(&self, pos: impl Into<Vec2i>) -> bool {
-        let pos = pos.into();
-        let (x, y) = (pos.x, pos.y);
-        // not outsize of the map
-        !(x < 0 || y < 0 || self.size[0] as i32 <= x || self.size[1] as i32 <= y)
+        self.body_blocks.contains(pos)
     }
 
+    /// True if it's outside of the map
     pub fn is_body_blocked(&self, pos: impl Into<Vec2i>) -> bool {
+        self.body_blocks.get(pos).cloned().unwrap_or(true)
+    }
+
+    /// True if it's outside of the map
+    pub fn is_view_blocked(&self, pos: impl Into<Vec2i>) -> bool {
+        self.view_blocks.get(pos).cloned().unwrap_or(true)
+    }
+
+    /// Sets blocks at the position. Returns false if it's outside of the map
+    pub fn set_blocks(
+        &mut self,
+        pos: impl Into<Vec2i>,
+        is_body_block: bool,
+        is_view_block: bool,
+    ) -> bool {
+        let pos = pos.into();
+        self.body_blocks.set(pos, is_body_block) && self.view_blocks.set(pos, is_view_block)
+    }
+
+    /// True if the cell is neither blocked nor unwalkable terrain (e.g. deep water)
+    pub fn is_walkable(&self, pos: impl Into<Vec2i>) -> bool {
         let pos = pos.into();
+        !self.is_body_blocked(pos) && self.terrain(pos).map_or(false, |t| t.is_walkable())
+    }
 
-        if !self.contains(pos) {
-            return true;
+    /// Fraction of light blocked by the cell. `1.0` if it's view block or outside of the map
+    ///
+    /// Door cells take it from their [`DoorState`] instead of the terrain, so that opened door
+    /// tiles don't block view.
+    pub fn light_block(&self, pos: impl Into<Vec2i>) -> f32 {
+        let pos = pos.into();
+        if self.is_view_blocked(pos) {
+            return 1.0;
         }
+        match self.door(pos) {
+            Some(state) if state.is_open() => 0.0,
+            Some(_) => 1.0,
+            None => self.terrain(pos).map_or(1.0, |t| t.light_block),
+        }
+    }
+}
 
-        let ix = pos.x + self.size[0] as i32 * pos.y;
-        self.body_blocks[ix as usize]
+/// Terrain
+impl RlMap {
+    /// Terrain of the cell. `None` if it's outside of the map
+    pub fn terrain(&self, pos: impl Into<Vec2i>) -> Option<&Terrain> {
+        self.terrain_ids
+            .get(pos)
+            .map(|id| &self.terrains[*id as usize])
     }
 
-    pub fn is_view_blocked(&self, pos: impl Into<Vec2i>) -> bool {
-        let pos = pos.into();
+    pub fn terrain_id(&self, pos: impl Into<Vec2i>) -> Option<TerrainId> {
+        self.terrain_ids.get(pos).cloned()
+    }
 
-        if !self.contains(pos) {
-            return true;
+    /// Adds a terrain to the table, returning the index of the same terrain if it already exists
+    pub fn add_terrain(&mut self, terrain: Terrain) -> TerrainId {
+        if let Some(ix) = self.terrains.iter().position(|t| *t == terrain) {
+            return ix as TerrainId;
         }
 
-        let ix = pos.x + self.size[0] as i32 * pos.y;
-        self.view_blocks[ix as usize]
+        self.terrains.push(terrain);
+        (self.terrains.len() - 1) as TerrainId
+    }
+
+    /// Sets terrain at the position. Returns false if it's outside of the map
+    pub fn set_terrain(&mut self, pos: impl Into<Vec2i>, id: TerrainId) -> bool {
+        let pos = pos.into();
+        if id as usize >= self.terrains.len() {
+            log::warn!("terrain {} is not in the table", id);
+            return false;
+        }
+        self.terrain_ids.set(pos, id)
+    }
+}
+
+/// Doors
+impl RlMap {
+    /// State of the door. `None` if it's not a door cell
+    pub fn door(&self, pos: impl Into<Vec2i>) -> Option<DoorState> {
+        self.doors.get(pos).cloned().flatten()
+    }
+
+    /// Sets the door state and the blocks. Closed doors block both body and view
+    ///
+    /// Returns false if it's outside of the map. Remember to refresh the FoV after changing doors.
+    pub fn set_door(&mut self, pos: impl Into<Vec2i>, state: DoorState) -> bool {
+        let pos = pos.into();
+        let is_block = !state.is_open();
+        self.doors.set(pos, Some(state)) && self.set_blocks(pos, is_block, is_block)
     }
 }
 
 /// FoV
 impl OpacityMap for RlMap {
     fn is_opaque(&self, pos: Vec2i) -> bool {
-        self.is_view_blocked(pos)
+        self.light_block(pos) >= 1.0
+    }
+
+    fn light_block(&self, pos: Vec2i) -> f32 {
+        <Self>::light_block(self, pos)
     }
 
     fn contains(&self, pos: Vec2i) -> bool {
@@ -61,6 +334,12 @@ impl OpacityMap for RlMap {
 /// IO
 impl RlMap {
     /// Load data requiring "meta" layer
+    ///
+    /// Tiles in the layer are read as blocks (`is-body-block` and `is-view-block` flags) and
+    /// [`Terrain`] attributes. Cells block view if their [`Terrain::light_block`] is `1.0`. Door
+    /// tiles are closed if they're body blocks and locked if they have `is-locked` flag.
+    ///
+    /// Infinite maps are bounded by their chunks (see [`tiled_grid_bounds`]).
     pub fn from_tiled(tiled: &tiled::Map) -> Self {
         let meta = tiled
             .layers
@@ -68,41 +347,462 @@ impl RlMap {
             .find(|l| l.name == "meta")
             .expect("layer with name `meta` is required");
 
-        let capacity = (tiled.width * tiled.height) as usize;
-        let mut body_blocks = Vec::with_capacity(capacity);
-        let mut view_blocks = Vec::with_capacity(capacity);
+        let bounds = self::tiled_grid_bounds(tiled);
+        let size = [bounds.w() as usize, bounds.h() as usize];
+        let mut map = Self::new(size);
 
-        let tiles = match &meta.tiles {
-            tiled::LayerData::Finite(f) => f,
-            tiled::LayerData::Infinite(_) => unimplemented!("tiled map infinite layer"),
-        };
+        // gid -> terrain
+        let mut terrain_ids = HashMap::<u32, TerrainId>::new();
 
         // fill the blocks
-        for (_y, row) in tiles.iter().enumerate() {
-            for (_x, layer_tile) in row.iter().enumerate() {
-                let gid = layer_tile.gid;
-                if gid == 0 {
-                    body_blocks.push(false);
-                    view_blocks.push(false);
-                    continue;
+        for (pos, layer_tile) in self::tiled_layer_tiles(meta, bounds.left_up()) {
+            let gid = layer_tile.gid;
+            if gid == 0 {
+                continue;
+            }
+
+            let tileset = tiled
+                .get_tileset_by_gid(gid)
+                .expect("no corresponding tileset for gid?");
+            let tile_id = gid - tileset.first_gid;
+            // tiles without properties are not listed
+            let tile = match tileset.tiles.iter().find(|t| t.id == tile_id) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            let props = &tile.properties;
+            let id = match terrain_ids.get(&gid) {
+                Some(id) => *id,
+                None => {
+                    let id = map.add_terrain(Terrain::from_tiled_props(props));
+                    terrain_ids.insert(gid, id);
+                    id
                 }
+            };
+            map.set_terrain(pos, id);
 
-                let tileset = tiled
-                    .get_tileset_by_gid(gid)
-                    .expect("no corresponding tileset for gid?");
-                let tile_ix = gid - tileset.first_gid;
-                let tile = &tileset.tiles[tile_ix as usize];
+            // `light-block` overrides `is-view-block`
+            let terrain = &map.terrains[id as usize];
+            let is_door = terrain.is_door;
+            let is_view_block = terrain.light_block >= 1.0;
+            map.set_blocks(
+                pos,
+                self::is_tiled_flag(props, "is-body-block"),
+                is_view_block,
+            );
 
-                body_blocks.push(tile.properties.keys().any(|k| k == "is-body-block"));
-                view_blocks.push(tile.properties.keys().any(|k| k == "is-view-block"));
+            if is_door {
+                let state = if self::is_tiled_flag(props, "is-locked") {
+                    DoorState::Locked
+                } else if self::is_tiled_flag(props, "is-body-block") {
+                    DoorState::Closed
+                } else {
+                    DoorState::Open
+                };
+                map.set_door(pos, state);
             }
         }
 
-        let size = [tiled.width as usize, tiled.height as usize];
-        Self {
-            size,
-            body_blocks,
-            view_blocks,
+        map
+    }
+}
+
+/// True if the bool property is set to `true`
+fn is_tiled_flag(props: &tiled::Properties, key: &str) -> bool {
+    matches!(props.get(key), Some(tiled::PropertyValue::BoolValue(true)))
+}
+
+/// Bounds of a Tiled map in Tiled's cell coordinates. Cells of [`RlMap`] are counted from the
+/// left-up corner
+///
+/// Infinite maps are bounded by the chunks of every layer.
+pub fn tiled_grid_bounds(tiled: &tiled::Map) -> Rect2i {
+    if !tiled.infinite {
+        return Rect2i::new([0, 0], [tiled.width, tiled.height]);
+    }
+
+    let chunks = tiled.layers.iter().flat_map(|l| match &l.tiles {
+        tiled::LayerData::Finite(_) => None,
+        tiled::LayerData::Infinite(chunks) => Some(chunks.values()),
+    });
+
+    let mut bounds: Option<[i32; 4]> = None;
+    for c in chunks.flatten() {
+        let [x1, y1, x2, y2] = [c.x, c.y, c.x + c.width as i32, c.y + c.height as i32];
+        bounds = Some(match bounds {
+            Some(b) => [b[0].min(x1), b[1].min(y1), b[2].max(x2), b[3].max(y2)],
+            None => [x1, y1, x2, y2],
+        });
+    }
+
+    match bounds {
+        Some([x1, y1, x2, y2]) => Rect2i::new([x1, y1], [(x2 - x1) as u32, (y2 - y1) as u32]),
+        None => Rect2i::new([0, 0], [0, 0]),
+    }
+}
+
+/// Tiles of a Tiled layer with positions in [`RlMap`]. `origin`: left-up corner of
+/// [`tiled_grid_bounds`]
+pub fn tiled_layer_tiles<'a>(
+    layer: &'a tiled::Layer,
+    origin: Vec2i,
+) -> Box<dyn Iterator<Item = (Vec2i, tiled::LayerTile)> + 'a> {
+    match &layer.tiles {
+        tiled::LayerData::Finite(rows) => Box::new(rows.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, tile)| (Vec2i::new(x as i32, y as i32), *tile))
+        })),
+        tiled::LayerData::Infinite(chunks) => Box::new(chunks.values().flat_map(move |c| {
+            let left_up = Vec2i::new(c.x, c.y) - origin;
+            c.tiles.iter().enumerate().flat_map(move |(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(x, tile)| (left_up + Vec2i::new(x as i32, y as i32), *tile))
+            })
+        })),
+    }
+}
+
+/// Tiles of a Tiled layer in an area in [`RlMap`] (see [`tiled_layer_tiles`]). Positions out of
+/// the layer are skipped
+pub fn tiled_layer_tiles_in<'a>(
+    layer: &'a tiled::Layer,
+    origin: Vec2i,
+    area: &Rect2i,
+) -> Box<dyn Iterator<Item = (Vec2i, tiled::LayerTile)> + 'a> {
+    let area = area.clone();
+    match &layer.tiles {
+        tiled::LayerData::Finite(_) => {
+            Box::new(area.iter_pos().filter_map(move |pos| {
+                self::tiled_layer_tile(layer, origin, pos).map(|t| (pos, t))
+            }))
+        }
+        tiled::LayerData::Infinite(chunks) => Box::new(chunks.values().flat_map(move |c| {
+            let bounds = Rect2i::new(Vec2i::new(c.x, c.y) - origin, [c.width, c.height]);
+            let area = bounds
+                .intersect(&area)
+                .unwrap_or_else(|| Rect2i::new([0, 0], [0, 0]));
+            area.iter_pos().map(move |pos| {
+                let local = pos - bounds.left_up();
+                (pos, c.tiles[local.y as usize][local.x as usize])
+            })
+        })),
+    }
+}
+
+/// Tile of a Tiled layer at a position in [`RlMap`]. `origin`: left-up corner of
+/// [`tiled_grid_bounds`]
+pub fn tiled_layer_tile(
+    layer: &tiled::Layer,
+    origin: Vec2i,
+    pos: Vec2i,
+) -> Option<tiled::LayerTile> {
+    match &layer.tiles {
+        tiled::LayerData::Finite(rows) => {
+            if pos.x < 0 || pos.y < 0 {
+                return None;
+            }
+            rows.get(pos.y as usize)?.get(pos.x as usize).cloned()
+        }
+        tiled::LayerData::Infinite(chunks) => {
+            let pos = pos + origin;
+            let c = chunks
+                .values()
+                .find(|c| Rect2i::new([c.x, c.y], [c.width, c.height]).contains(pos))?;
+            let row = c.tiles.get((pos.y - c.y) as usize)?;
+            row.get((pos.x - c.x) as usize).cloned()
+        }
+    }
+}
+
+/// Tile of a Tiled layer at a position in [`RlMap`]. `origin`: left-up corner of
+/// [`tiled_grid_bounds`]
+pub fn tiled_layer_tile_mut(
+    layer: &mut tiled::Layer,
+    origin: Vec2i,
+    pos: Vec2i,
+) -> Option<&mut tiled::LayerTile> {
+    match &mut layer.tiles {
+        tiled::LayerData::Finite(rows) => {
+            if pos.x < 0 || pos.y < 0 {
+                return None;
+            }
+            rows.get_mut(pos.y as usize)?.get_mut(pos.x as usize)
+        }
+        tiled::LayerData::Infinite(chunks) => {
+            let pos = pos + origin;
+            let c = chunks
+                .values_mut()
+                .find(|c| Rect2i::new([c.x, c.y], [c.width, c.height]).contains(pos))?;
+            let row = c.tiles.get_mut((pos.y - c.y) as usize)?;
+            row.get_mut((pos.x - c.x) as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::shadow::{self, FovAlgorithm, FovData, FovRefreshParams};
+
+    fn is_in_view(map: &RlMap, origin: Vec2i, pos: Vec2i) -> bool {
+        let mut fov = FovData::new(8, 8);
+        shadow::refresh_fov(
+            &mut fov,
+            FovRefreshParams {
+                r: 8,
+                origin,
+                opa: map,
+                algo: FovAlgorithm::Symmetric,
+            },
+        );
+        fov.is_in_view(pos)
+    }
+
+    fn props(props: &[(&str, tiled::PropertyValue)]) -> tiled::Properties {
+        props
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_terrain_from_tiled_props() {
+        use tiled::PropertyValue as P;
+
+        let terrain = Terrain::from_tiled_props(&self::props(&[]));
+        assert_eq!(terrain, Terrain::default());
+
+        // move cost is at least one
+        for cost in &[0, -3] {
+            let terrain =
+                Terrain::from_tiled_props(&self::props(&[("move-cost", P::IntValue(*cost))]));
+            assert_eq!(terrain.move_cost, 1);
         }
+
+        let terrain = Terrain::from_tiled_props(&self::props(&[
+            ("move-cost", P::IntValue(3)),
+            ("liquid", P::StringValue("deep".to_string())),
+            ("stairs", P::StringValue("down".to_string())),
+            ("hazard-damage", P::IntValue(-2)),
+            ("is-door", P::BoolValue(true)),
+        ]));
+        assert_eq!(
+            terrain,
+            Terrain {
+                move_cost: 3,
+                liquid: Some(Liquid::Deep),
+                is_door: true,
+                stairs: Some(StairsKind::Down),
+                hazard_damage: 0,
+                light_block: 0.0,
+            }
+        );
+
+        // unknown values are ignored
+        let terrain = Terrain::from_tiled_props(&self::props(&[
+            ("liquid", P::StringValue("lava".to_string())),
+            ("stairs", P::StringValue("sideways".to_string())),
+        ]));
+        assert_eq!(terrain.liquid, None);
+        assert_eq!(terrain.stairs, None);
+    }
+
+    #[test]
+    fn test_terrain_light_block() {
+        use tiled::PropertyValue as P;
+
+        let view_block = ("is-view-block", P::BoolValue(true));
+
+        let terrain = Terrain::from_tiled_props(&self::props(&[view_block.clone()]));
+        assert_eq!(terrain.light_block, 1.0);
+
+        // `light-block` overrides `is-view-block`
+        let terrain = Terrain::from_tiled_props(&self::props(&[
+            view_block.clone(),
+            ("light-block", P::FloatValue(0.5)),
+        ]));
+        assert_eq!(terrain.light_block, 0.5);
+
+        // clamped to [0.0, 1.0]
+        let terrain =
+            Terrain::from_tiled_props(&self::props(&[("light-block", P::FloatValue(2.0))]));
+        assert_eq!(terrain.light_block, 1.0);
+        let terrain =
+            Terrain::from_tiled_props(&self::props(&[("light-block", P::FloatValue(-1.0))]));
+        assert_eq!(terrain.light_block, 0.0);
+    }
+
+    #[test]
+    fn test_from_tiled() {
+        let tiles = r#"
+  <tile id="0"><properties>
+   <property name="is-body-block" type="bool" value="true"/>
+   <property name="is-view-block" type="bool" value="true"/>
+  </properties></tile>
+  <tile id="1"><properties>
+   <property name="is-view-block" type="bool" value="true"/>
+   <property name="light-block" type="float" value="0.5"/>
+  </properties></tile>
+  <tile id="2"><properties>
+   <property name="is-body-block" type="bool" value="false"/>
+   <property name="is-view-block" type="bool" value="false"/>
+  </properties></tile>
+  <tile id="3"><properties>
+   <property name="is-door" type="bool" value="true"/>
+   <property name="is-body-block" type="bool" value="true"/>
+   <property name="is-view-block" type="bool" value="true"/>
+   <property name="is-locked" type="bool" value="false"/>
+  </properties></tile>"#;
+
+        let src = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="4" height="1" tilewidth="32" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="meta" tilewidth="32" tileheight="32" tilecount="4" columns="4">
+  <image source="meta.png" width="128" height="32"/>
+{}
+ </tileset>
+ <layer id="1" name="meta" width="4" height="1">
+  <data encoding="csv">1,2,3,4</data>
+ </layer>
+</map>"#,
+            tiles
+        );
+        let map = RlMap::from_tiled(&tiled::parse(src.as_bytes()).unwrap());
+
+        // wall
+        assert!(map.is_body_blocked([0, 0]));
+        assert!(map.is_view_blocked([0, 0]));
+        assert_eq!(map.light_block([0, 0]), 1.0);
+
+        // `light-block` overrides `is-view-block`
+        assert!(!map.is_body_blocked([1, 0]));
+        assert!(!map.is_view_blocked([1, 0]));
+        assert_eq!(map.light_block([1, 0]), 0.5);
+
+        // `false` flags
+        assert!(!map.is_body_blocked([2, 0]));
+        assert!(!map.is_view_blocked([2, 0]));
+        assert_eq!(map.light_block([2, 0]), 0.0);
+
+        // not locked with `is-locked = false`
+        assert_eq!(map.door([3, 0]), Some(DoorState::Closed));
+        assert_eq!(map.light_block([3, 0]), 1.0);
+    }
+
+    /// Infinite map with chunks at negative positions and a gap between them
+    fn chunked_tmx() -> tiled::Map {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="16" height="16" tilewidth="32" tileheight="32" infinite="1">
+ <layer id="1" name="meta" width="8" height="4">
+  <data encoding="csv">
+   <chunk x="-4" y="-2" width="4" height="2">1,2,3,4,5,6,7,8</chunk>
+   <chunk x="0" y="0" width="4" height="2">9,10,11,12,13,14,15,16</chunk>
+  </data>
+ </layer>
+ <layer id="2" name="ground" width="2" height="2">
+  <data encoding="csv">
+   <chunk x="-8" y="1" width="2" height="2">0,0,0,0</chunk>
+  </data>
+ </layer>
+</map>"#;
+
+        tiled::parse(src.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_tiled_grid_bounds() {
+        // union of the chunks of every layer
+        let map = self::chunked_tmx();
+        assert_eq!(tiled_grid_bounds(&map), Rect2i::new([-8, -2], [12, 5]));
+    }
+
+    #[test]
+    fn test_tiled_layer_tile() {
+        let mut map = self::chunked_tmx();
+        let origin = tiled_grid_bounds(&map).left_up();
+        let layer = &mut map.layers[0];
+        let gid = |layer: &tiled::Layer, pos: [i32; 2]| {
+            tiled_layer_tile(layer, origin, pos.into()).map(|t| t.gid)
+        };
+
+        // the last tile of the first chunk and the first tile of the second chunk
+        assert_eq!(gid(layer, [4, 0]), Some(1));
+        assert_eq!(gid(layer, [7, 1]), Some(8));
+        assert_eq!(gid(layer, [8, 2]), Some(9));
+        // gap between the chunks and outside of the map
+        assert_eq!(gid(layer, [8, 1]), None);
+        assert_eq!(gid(layer, [7, 2]), None);
+        assert_eq!(gid(layer, [-1, 0]), None);
+        assert_eq!(gid(layer, [12, 2]), None);
+
+        // tiles in an area over the chunks
+        let mut tiles = tiled_layer_tiles_in(layer, origin, &Rect2i::new([6, 1], [4, 2]))
+            .map(|(pos, t)| (pos, t.gid))
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(pos, _gid)| (pos.y, pos.x));
+        assert_eq!(
+            tiles,
+            vec![
+                (Vec2i::new(6, 1), 7),
+                (Vec2i::new(7, 1), 8),
+                (Vec2i::new(8, 2), 9),
+                (Vec2i::new(9, 2), 10),
+            ]
+        );
+
+        // write across the chunk boundary
+        tiled_layer_tile_mut(layer, origin, Vec2i::new(7, 1))
+            .unwrap()
+            .gid = 100;
+        tiled_layer_tile_mut(layer, origin, Vec2i::new(8, 2))
+            .unwrap()
+            .gid = 200;
+        assert!(tiled_layer_tile_mut(layer, origin, Vec2i::new(8, 1)).is_none());
+
+        assert_eq!(gid(layer, [7, 1]), Some(100));
+        assert_eq!(gid(layer, [8, 2]), Some(200));
+        assert_eq!(gid(layer, [6, 1]), Some(7));
+        assert_eq!(gid(layer, [9, 2]), Some(10));
+
+        let tiles = tiled_layer_tiles(layer, origin).collect::<Vec<_>>();
+        assert_eq!(tiles.len(), 16);
+        assert!(tiles
+            .iter()
+            .any(|(pos, t)| *pos == Vec2i::new(8, 2) && t.gid == 200));
+    }
+
+    #[test]
+    fn test_door_light_block() {
+        let mut map = crate::rl::ascii::parse(
+            "
+#######
+#..+..#
+#######
+",
+        )
+        .unwrap()
+        .map;
+
+        // door tile loaded from Tiled with `is-view-block`
+        let door = Vec2i::new(3, 1);
+        let id = map.add_terrain(Terrain {
+            light_block: 1.0,
+            ..Terrain::door()
+        });
+        map.set_terrain(door, id);
+
+        let (from, to) = (Vec2i::new(1, 1), Vec2i::new(5, 1));
+        assert_eq!(map.light_block(door), 1.0);
+        assert!(!self::is_in_view(&map, from, to));
+
+        map.set_door(door, DoorState::Open);
+        assert_eq!(map.light_block(door), 0.0);
+        assert!(self::is_in_view(&map, from, to));
+
+        map.set_door(door, DoorState::Closed);
+        assert!(!self::is_in_view(&map, from, to));
     }
 }
@@ -1,9 +1,13 @@
 /*!
-Field of view (FoV) and fog of war (FoW)
+Field of view (FoV), fog of war (FoW), lighting and remembered map
  */
 
 mod fov;
 mod fow;
+mod light;
+mod memory;
 
 pub use fov::*;
 pub use fow::*;
+pub use light::*;
+pub use memory::*;
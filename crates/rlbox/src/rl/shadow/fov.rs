@@ -2,12 +2,22 @@
 Field of view
 */
 
-use crate::rl::grid2d::Vec2i;
+use serde::{Deserialize, Serialize};
+
+use crate::rl::grid2d::{Grid2d, Vec2i};
 
 /// Refreshes [`FovWrite`] (FoV data or maybe bundle of FoV and FoW)
 pub fn refresh_fov<T: OpacityMap>(fov: &mut impl FovWrite, params: FovRefreshParams<T>) {
     fov.on_refresh(&params);
-    self::update_fov(fov, params.r, params.origin, params.opa);
+    match params.algo {
+        FovAlgorithm::Shadowcast => {
+            self::update_fov(fov, params.r, params.origin, params.opa);
+        }
+        FovAlgorithm::Symmetric | FovAlgorithm::DiamondWalls => {
+            let is_symmetric = params.algo == FovAlgorithm::Symmetric;
+            self::update_fov_sym(fov, params.r, params.origin, params.opa, is_symmetric);
+        }
+    }
 }
 
 /// FoV data or maybe bundle of FoV and FoW
@@ -26,30 +36,68 @@ pub struct FovRefreshParams<'a, T: OpacityMap> {
     pub r: u32,
     pub origin: Vec2i,
     pub opa: &'a T,
+    pub algo: FovAlgorithm,
+}
+
+/// Shadowcasting variant, which decides what is fair in the game rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FovAlgorithm {
+    /// Recursive shadowcasting with permissive scan for walls. Not symmetric: a monster can see you
+    /// while you can't see it
+    Shadowcast,
+    /// Symmetric shadowcasting. A floor is visible if and only if its center is in view, so if A
+    /// sees B, B sees A
+    Symmetric,
+    /// Shadowcasting treating walls as diamonds. A floor is visible if any part of it is in view,
+    /// which is more permissive than [`FovAlgorithm::Symmetric`] but not symmetric
+    DiamondWalls,
+}
+
+impl Default for FovAlgorithm {
+    fn default() -> Self {
+        Self::Shadowcast
+    }
 }
 
 /// Map bounds and opacities
 pub trait OpacityMap {
     fn is_opaque(&self, pos: Vec2i) -> bool;
     fn contains(&self, pos: Vec2i) -> bool;
+
+    /// Fraction of light blocked by the cell in range [0.0, 1.0] (e.g. smoke and foliage)
+    fn light_block(&self, pos: Vec2i) -> f32 {
+        if self.is_opaque(pos) {
+            1.0
+        } else {
+            0.0
+        }
+    }
 }
 
 /// Stub implementation of [`FovWrite`]
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FovData {
-    is_visible: Vec<bool>,
+    /// Cells around the origin, which is at [radius, radius]
+    is_visible: Grid2d<bool>,
     pub radius: u32,
     /// Where the character is
     pub origin: Vec2i,
 }
 
+impl Default for FovData {
+    /// Radius zero, lighting only the origin
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
 impl FovData {
+    /// Allocates buffer for `max_radius`. It grows on refresh if the radius exceeds it
     pub fn new(radius: u32, max_radius: u32) -> Self {
-        let edge = max_radius * 2 + 1;
-        let data = vec![false; (edge * edge) as usize];
+        let edge = (std::cmp::max(radius, max_radius) * 2 + 1) as usize;
 
         Self {
-            is_visible: data,
+            is_visible: Grid2d::new([edge, edge], false),
             origin: Vec2i::default(),
             radius,
         }
@@ -60,23 +108,33 @@ impl FovData {
     }
 
     pub fn clear(&mut self) {
-        for i in 0..self.is_visible.len() {
-            self.is_visible[i] = false;
-        }
+        self.is_visible.fill(false);
     }
 
     pub fn radius(&self) -> u32 {
         self.radius
     }
 
+    /// Maximum radius without reallocation
+    pub fn capacity(&self) -> u32 {
+        (self.is_visible.w() as u32).saturating_sub(1) / 2
+    }
+
+    /// Reallocates the buffer if the radius exceeds the capacity. Visibility is cleared then
+    pub fn reserve(&mut self, radius: u32) {
+        if radius > self.capacity() {
+            let edge = (radius * 2 + 1) as usize;
+            self.is_visible = Grid2d::new([edge, edge], false);
+        }
+    }
+
     pub fn origin(&self) -> Vec2i {
         self.origin
     }
 
-    fn ix(&self, pos: Vec2i) -> usize {
-        let edge = self.radius * 2 + 1;
-        let delta = pos - self.origin + Vec2i::new(self.radius as i32, self.radius as i32);
-        (delta.x as u32 + delta.y as u32 * edge) as usize
+    /// Absolute position to the position in `is_visible`
+    fn local(&self, pos: Vec2i) -> Vec2i {
+        pos - self.origin + Vec2i::new(self.radius as i32, self.radius as i32)
     }
 
     pub fn is_in_view(&self, pos: Vec2i) -> bool {
@@ -84,8 +142,8 @@ impl FovData {
         if delta.len_king() > self.radius {
             false
         } else {
-            let ix = self.ix(pos);
-            self.is_visible[ix]
+            let local = self.local(pos);
+            self.is_visible.get(local).cloned().unwrap_or(false)
         }
     }
 
@@ -94,11 +152,9 @@ impl FovData {
 
         for y in 0..(2 * self.radius + 1) {
             for x in 0..(2 * self.radius + 1) {
-                let ix = x + y * (2 * self.radius + 1);
-
                 let ch = if x == self.radius && y == self.radius {
                     "@"
-                } else if self.is_visible[ix as usize] {
+                } else if self.is_visible[[x as i32, y as i32]] {
                     " "
                 } else {
                     "x"
@@ -114,16 +170,16 @@ impl FovData {
 
 impl FovWrite for FovData {
     fn on_refresh<T: OpacityMap>(&mut self, params: &FovRefreshParams<T>) {
+        self.reserve(params.r);
         self.radius = params.r;
         self.origin = params.origin;
-        // TODO: resize if needed
 
         self.clear();
     }
 
     fn light(&mut self, pos: Vec2i) {
-        let ix = self.ix(pos);
-        self.is_visible[ix] = true;
+        let local = self.local(pos);
+        self.is_visible[local] = true;
     }
 }
 
@@ -333,3 +389,306 @@ impl Octant {
         [A, B, C, D, E, F, G, H]
     }
 }
+
+// --------------------------------------------------------------------------------
+// Symmetric shadowcasting
+
+/// Shadowcasting scanning rows of four quadrants. Walls block the view between their left and right
+/// edges measured at the center line of the row, which makes them diamonds.
+fn update_fov_sym(
+    fov: &mut impl FovWrite,
+    r: u32,
+    origin: Vec2i,
+    opa: &impl OpacityMap,
+    is_symmetric: bool,
+) {
+    fov.light(origin);
+    for quad in &Quadrant::clockwise() {
+        let mut scx = SymScanContext {
+            r,
+            origin,
+            quad: *quad,
+            fov: &mut *fov,
+            opa,
+            is_symmetric,
+        };
+        let row = SymRow {
+            depth: 1,
+            start: Slope::new(-1, 1),
+            end: Slope::new(1, 1),
+        };
+        scx.scan(row);
+    }
+}
+
+struct SymScanContext<'a, Fov: FovWrite, Opa: OpacityMap> {
+    r: u32,
+    origin: Vec2i,
+    quad: Quadrant,
+    fov: &'a mut Fov,
+    opa: &'a Opa,
+    /// Light floors only if their centers are in view
+    is_symmetric: bool,
+}
+
+impl<'a, Fov: FovWrite, Opa: OpacityMap> SymScanContext<'a, Fov, Opa> {
+    /// (depth, column) -> (absolute grid position)
+    fn dc2abs(&self, depth: i32, col: i32) -> Vec2i {
+        let [row, col_unit] = self.quad.to_units();
+        self.origin + depth * row + col * col_unit
+    }
+
+    /// Cells outside of the map are considered as walls
+    fn is_wall(&self, pos: Vec2i) -> bool {
+        !self.opa.contains(pos) || self.opa.is_opaque(pos)
+    }
+
+    fn is_in_radius(&self, depth: i32, col: i32) -> bool {
+        let r = self.r as f32 + 0.5;
+        (depth * depth + col * col) as f32 <= r * r
+    }
+
+    fn scan(&mut self, mut row: SymRow) {
+        if row.depth > self.r as i32 {
+            return;
+        }
+
+        // was the previous cell a wall?
+        let mut prev_wall = Option::<bool>::None;
+
+        for col in row.cols() {
+            let pos = self.dc2abs(row.depth, col);
+            let is_wall = self.is_wall(pos);
+
+            let is_visible = is_wall || !self.is_symmetric || row.is_symmetric(col);
+            if is_visible && self.opa.contains(pos) && self.is_in_radius(row.depth, col) {
+                self.fov.light(pos);
+            }
+
+            match prev_wall {
+                Some(true) if !is_wall => {
+                    row.start = Slope::of_tile(row.depth, col);
+                }
+                Some(false) if is_wall => {
+                    let mut next = row.next();
+                    next.end = Slope::of_tile(row.depth, col);
+                    self.scan(next);
+                }
+                _ => {}
+            }
+
+            prev_wall = Some(is_wall);
+        }
+
+        if prev_wall == Some(false) {
+            self.scan(row.next());
+        }
+    }
+}
+
+/// Row in a quadrant with slopes of view
+#[derive(Debug, Clone)]
+struct SymRow {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl SymRow {
+    fn cols(&self) -> std::ops::RangeInclusive<i32> {
+        let min = self.start.round_ties_up(self.depth);
+        let max = self.end.round_ties_down(self.depth);
+        min..=max
+    }
+
+    fn next(&self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// If the center of the cell is in view
+    fn is_symmetric(&self, col: i32) -> bool {
+        self.start.le_cell(self.depth, col) && self.end.ge_cell(self.depth, col)
+    }
+}
+
+/// Exact slope (column / depth) in a rational number to avoid precision problems
+#[derive(Debug, Clone, Copy)]
+struct Slope {
+    num: i32,
+    /// Positive
+    den: i32,
+}
+
+impl Slope {
+    fn new(num: i32, den: i32) -> Self {
+        Self { num, den }
+    }
+
+    /// Slope to the left edge of the cell at the center line of the row
+    fn of_tile(depth: i32, col: i32) -> Self {
+        Self::new(2 * col - 1, 2 * depth)
+    }
+
+    /// `floor(depth * slope + 0.5)`
+    fn round_ties_up(&self, depth: i32) -> i32 {
+        (2 * depth * self.num + self.den).div_euclid(2 * self.den)
+    }
+
+    /// `ceil(depth * slope - 0.5)`
+    fn round_ties_down(&self, depth: i32) -> i32 {
+        -(-(2 * depth * self.num - self.den)).div_euclid(2 * self.den)
+    }
+
+    /// `slope <= col / depth`
+    fn le_cell(&self, depth: i32, col: i32) -> bool {
+        self.num * depth <= col * self.den
+    }
+
+    /// `slope >= col / depth`
+    fn ge_cell(&self, depth: i32, col: i32) -> bool {
+        self.num * depth >= col * self.den
+    }
+}
+
+/// Clockwise
+#[derive(Debug, Clone, Copy)]
+enum Quadrant {
+    N,
+    E,
+    S,
+    W,
+}
+
+impl Quadrant {
+    /// Units of (depth, column)
+    pub fn to_units(&self) -> [Vec2i; 2] {
+        match self {
+            Quadrant::N => [Vec2i::new(0, -1), Vec2i::new(1, 0)],
+            Quadrant::E => [Vec2i::new(1, 0), Vec2i::new(0, 1)],
+            Quadrant::S => [Vec2i::new(0, 1), Vec2i::new(-1, 0)],
+            Quadrant::W => [Vec2i::new(-1, 0), Vec2i::new(0, -1)],
+        }
+    }
+
+    pub const fn clockwise() -> [Self; 4] {
+        use Quadrant::*;
+        [N, E, S, W]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::rlmap::RlMap;
+
+    /// Room with pillars (see [`crate::rl::ascii`])
+    const PILLARS: &str = "
+############
+#..........#
+#..#...#...#
+#......#...#
+#.#..#.....#
+#......##..#
+#..#.......#
+############
+";
+
+    fn map(src: &str) -> RlMap {
+        crate::rl::ascii::parse(src).unwrap().map
+    }
+
+    fn fov(map: &RlMap, origin: Vec2i, algo: FovAlgorithm) -> FovData {
+        let r = 12;
+        let mut fov = FovData::new(r, r);
+        self::refresh_fov(
+            &mut fov,
+            FovRefreshParams {
+                r,
+                origin,
+                opa: map,
+                algo,
+            },
+        );
+        fov
+    }
+
+    fn floors(map: &RlMap) -> Vec<Vec2i> {
+        let [w, h] = map.size();
+        (0..h as i32)
+            .flat_map(|y| (0..w as i32).map(move |x| Vec2i::new(x, y)))
+            .filter(|pos| !map.is_opaque(*pos))
+            .collect()
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let map = self::map(PILLARS);
+        let floors = self::floors(&map);
+        let fovs = floors
+            .iter()
+            .map(|pos| self::fov(&map, *pos, FovAlgorithm::Symmetric))
+            .collect::<Vec<_>>();
+
+        // pillars hide some floors
+        assert!(fovs
+            .iter()
+            .any(|fov| floors.iter().any(|pos| !fov.is_in_view(*pos))));
+
+        for (a, fov_a) in floors.iter().zip(&fovs) {
+            for (b, fov_b) in floors.iter().zip(&fovs) {
+                assert_eq!(
+                    fov_a.is_in_view(*b),
+                    fov_b.is_in_view(*a),
+                    "{:?} and {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_diamond_walls_superset() {
+        let map = self::map(PILLARS);
+        let [w, h] = map.size();
+
+        for origin in self::floors(&map) {
+            let sym = self::fov(&map, origin, FovAlgorithm::Symmetric);
+            let diamond = self::fov(&map, origin, FovAlgorithm::DiamondWalls);
+
+            for y in 0..h as i32 {
+                for x in 0..w as i32 {
+                    let pos = Vec2i::new(x, y);
+                    assert!(!sym.is_in_view(pos) || diamond.is_in_view(pos), "{:?}", pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        let mut fov = FovData::default();
+        fov.light(fov.origin());
+        assert!(fov.is_in_view(Vec2i::default()));
+
+        // grows on refresh
+        let map = self::map(PILLARS);
+        let origin = Vec2i::new(5, 3);
+        self::refresh_fov(
+            &mut fov,
+            FovRefreshParams {
+                r: 4,
+                origin,
+                opa: &map,
+                algo: FovAlgorithm::Shadowcast,
+            },
+        );
+        assert_eq!(fov.capacity(), 4);
+        assert!(fov.is_in_view(origin));
+        assert!(fov.is_in_view(origin + Vec2i::new(-3, 0)));
+    }
+}
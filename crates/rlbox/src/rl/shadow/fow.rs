@@ -3,95 +3,159 @@ Fog of war, shadow on the map
 */
 
 use crate::rl::{
-    grid2d::Vec2i,
-    shadow::fov::{self, FovData, FovWrite, OpacityMap},
+    grid2d::{Grid2d, Vec2i},
+    shadow::{
+        fov::{self, FovData, FovWrite, OpacityMap},
+        light::LightMap,
+    },
 };
 
 /// Fog of war, shadow on the map
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FowData {
-    /// [w, h]
-    map_size: [usize; 2],
     /// True if uncovered
-    shadows: Vec<bool>,
+    shadows: Grid2d<bool>,
 }
 
 impl FowData {
     pub fn new(size: [usize; 2]) -> Self {
         FowData {
-            map_size: size,
-            shadows: vec![false; size[0] * size[1]],
+            shadows: Grid2d::new(size, false),
         }
     }
 
-    pub fn clear(&mut self) {
-        let area = self.map_size[0] * self.map_size[1];
-        for i in 0..area {
-            self.shadows[i] = false;
-        }
+    /// [w, h]
+    pub fn size(&self) -> [usize; 2] {
+        self.shadows.size()
     }
 
-    fn ix(&self, pos: [usize; 2]) -> usize {
-        pos[0] + pos[1] * self.map_size[0]
+    pub fn clear(&mut self) {
+        self.shadows.fill(false);
     }
 
-    pub fn cover(&mut self, pos: [usize; 2]) {
-        let ix = self.ix(pos);
-        if ix >= self.shadows.len() {
-            log::warn!("tried to  position out of the map: {:?}", pos);
-        } else {
-            self.shadows[ix] = false;
+    pub fn cover(&mut self, pos: impl Into<Vec2i>) {
+        let pos = pos.into();
+        if !self.shadows.set(pos, false) {
+            log::warn!("tried to cover position out of the map: {:?}", pos);
         }
     }
 
-    pub fn uncover(&mut self, pos: [usize; 2]) {
-        let ix = self.ix(pos);
-        if ix >= self.shadows.len() {
+    pub fn uncover(&mut self, pos: impl Into<Vec2i>) {
+        let pos = pos.into();
+        if !self.shadows.set(pos, true) {
             log::warn!("tried to uncover position out of the map: {:?}", pos);
-        } else {
-            self.shadows[ix] = true;
         }
     }
 
-    // TODO: maybe prefer u32
-    pub fn is_visible(&self, pos: [usize; 2]) -> bool {
-        let ix = self.ix(pos);
-        if ix >= self.shadows.len() {
-            false
-        } else {
-            self.shadows[ix]
-        }
+    /// False if it's outside of the map
+    pub fn is_visible(&self, pos: impl Into<Vec2i>) -> bool {
+        self.shadows.get(pos).cloned().unwrap_or(false)
     }
 }
 
 /// Updates FoV and FoW with one iteration
+///
+/// FoW is uncovered only where the `light` map is lit (every cell in view if it's `None`), so
+/// that dark rooms are not revealed by just looking into them.
 pub fn refresh_fov_fow<'a, 'b>(
     fov: &'a mut FovData,
     fow: &'b mut FowData,
+    light: Option<&LightMap>,
     r: Option<u32>,
     origin: Vec2i,
     opa: &impl fov::OpacityMap,
+    algo: fov::FovAlgorithm,
 ) {
     let r = r.unwrap_or(fov.radius());
 
-    let mut bind = FovFowWrite { fov, fow };
-    let params = fov::FovRefreshParams { r, origin, opa };
+    let mut bind = FovFowWrite { fov, fow, light };
+    let params = fov::FovRefreshParams {
+        r,
+        origin,
+        opa,
+        algo,
+    };
 
     fov::refresh_fov(&mut bind, params);
 }
 
-struct FovFowWrite<'a, 'b> {
+struct FovFowWrite<'a, 'b, 'c> {
     fov: &'a mut FovData,
     fow: &'b mut FowData,
+    light: Option<&'c LightMap>,
 }
 
-impl<'a, 'b> FovWrite for FovFowWrite<'a, 'b> {
+impl<'a, 'b, 'c> FovWrite for FovFowWrite<'a, 'b, 'c> {
     fn on_refresh<T: OpacityMap>(&mut self, params: &fov::FovRefreshParams<T>) {
         self.fov.on_refresh(params);
     }
 
     fn light(&mut self, pos: Vec2i) {
         self.fov.light(pos);
-        self.fow.uncover([pos.x as usize, pos.y as usize]);
+        if self.light.map_or(true, |l| l.is_lit(pos)) {
+            self.fow.uncover(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rl::shadow::{FovAlgorithm, LightEmitter};
+
+    #[test]
+    fn test_dark_room() {
+        // lit room on the left and dark room on the right
+        let map = crate::rl::ascii::parse(
+            "
+###########
+#....'....#
+#....#....#
+###########
+",
+        )
+        .unwrap()
+        .map;
+        let size = map.size();
+
+        let mut light = LightMap::new(size, [0.0; 3]);
+        light.calculate(
+            &[LightEmitter::new([2, 1], 3)],
+            &map,
+            FovAlgorithm::Symmetric,
+        );
+
+        let origin = Vec2i::new(3, 1);
+        let mut fov = FovData::new(10, 10);
+        let mut fow = FowData::new(size);
+        self::refresh_fov_fow(
+            &mut fov,
+            &mut fow,
+            Some(&light),
+            None,
+            origin,
+            &map,
+            FovAlgorithm::Symmetric,
+        );
+
+        let dark = Vec2i::new(8, 1);
+        assert!(fov.is_in_view(dark));
+        assert!(!light.is_lit(dark));
+        assert!(!fow.is_visible(dark));
+        assert!(fow.is_visible(origin));
+        assert!(fow.is_visible([1, 2]));
+
+        // without lighting, everything in view is uncovered
+        let mut fow = FowData::new(size);
+        self::refresh_fov_fow(
+            &mut fov,
+            &mut fow,
+            None,
+            None,
+            origin,
+            &map,
+            FovAlgorithm::Symmetric,
+        );
+        assert!(fow.is_visible(dark));
     }
 }
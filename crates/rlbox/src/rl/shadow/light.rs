@@ -0,0 +1,185 @@
+/*!
+Light sources and lit area
+
+Each [`LightEmitter`] lights cells in its field of view. The player sees cells that are both in the
+player's FoV and lit (see [`LightMap::is_lit`]). Light behind partially light-blocking cells (see
+[`OpacityMap::light_block`]) is dimmed.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::rl::{
+    grid2d::{Grid2d, Vec2i},
+    line,
+    shadow::fov::{self, FovAlgorithm, FovRefreshParams, FovWrite, OpacityMap},
+};
+
+/// How light gets darker with distance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Falloff {
+    /// Same brightness in the radius
+    Constant,
+    /// Linearly darker to the edge of the radius
+    Linear,
+    /// Quadratically darker to the edge of the radius
+    Quadratic,
+}
+
+impl Falloff {
+    /// Brightness factor in range [0.0, 1.0]
+    pub fn attenuate(&self, dist: f32, radius: f32) -> f32 {
+        // (the edge cells are a bit lit)
+        let x = (dist / (radius + 1.0)).min(1.0);
+        match self {
+            Self::Constant => 1.0,
+            Self::Linear => 1.0 - x,
+            Self::Quadratic => (1.0 - x) * (1.0 - x),
+        }
+    }
+}
+
+/// Light source such as torches and glowing monsters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightEmitter {
+    pub pos: Vec2i,
+    pub radius: u32,
+    /// Linear RGB
+    pub color: [f32; 3],
+    /// Brightness at the center
+    pub intensity: f32,
+    pub falloff: Falloff,
+}
+
+impl LightEmitter {
+    /// White light with linear falloff
+    pub fn new(pos: impl Into<Vec2i>, radius: u32) -> Self {
+        Self {
+            pos: pos.into(),
+            radius,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            falloff: Falloff::Linear,
+        }
+    }
+}
+
+/// Light levels of cells combining every light emitter
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightMap {
+    /// Linear RGB, not clamped
+    cells: Grid2d<[f32; 3]>,
+    /// Light level of every cell
+    pub ambient: [f32; 3],
+    /// Cells with light level (max of RGB) less than this are dark
+    pub threshold: f32,
+}
+
+impl LightMap {
+    pub fn new(size: [usize; 2], ambient: [f32; 3]) -> Self {
+        Self {
+            cells: Grid2d::new(size, ambient),
+            ambient,
+            threshold: 0.1,
+        }
+    }
+
+    /// Every cell is lit (no lighting)
+    pub fn bright(size: [usize; 2]) -> Self {
+        Self::new(size, [1.0, 1.0, 1.0])
+    }
+
+    pub fn size(&self) -> [usize; 2] {
+        self.cells.size()
+    }
+
+    /// Resets cells to the ambient light
+    pub fn clear(&mut self) {
+        self.cells.fill(self.ambient);
+    }
+
+    /// Adds light in the field of view of the emitter
+    pub fn add(&mut self, emitter: &LightEmitter, opa: &impl OpacityMap, algo: FovAlgorithm) {
+        let mut write = LightWrite {
+            cells: &mut self.cells,
+            emitter,
+            opa,
+        };
+
+        let params = FovRefreshParams {
+            r: emitter.radius,
+            origin: emitter.pos,
+            opa,
+            algo,
+        };
+
+        fov::refresh_fov(&mut write, params);
+    }
+
+    /// Clears and adds every emitter
+    pub fn calculate<'a>(
+        &mut self,
+        emitters: impl IntoIterator<Item = &'a LightEmitter>,
+        opa: &impl OpacityMap,
+        algo: FovAlgorithm,
+    ) {
+        self.clear();
+        for e in emitters {
+            self.add(e, opa, algo);
+        }
+    }
+
+    /// Light color clamped to [0.0, 1.0]. Black if it's outside of the map
+    pub fn color(&self, pos: impl Into<Vec2i>) -> [f32; 3] {
+        match self.cells.get(pos) {
+            Some(c) => [c[0].min(1.0), c[1].min(1.0), c[2].min(1.0)],
+            None => [0.0, 0.0, 0.0],
+        }
+    }
+
+    /// Brightness (max of RGB) in range [0.0, 1.0]
+    pub fn level(&self, pos: impl Into<Vec2i>) -> f32 {
+        let c = self.color(pos);
+        c[0].max(c[1]).max(c[2])
+    }
+
+    pub fn is_lit(&self, pos: impl Into<Vec2i>) -> bool {
+        self.level(pos) >= self.threshold
+    }
+}
+
+/// Adds light of an emitter
+struct LightWrite<'a, 'b, 'c, O> {
+    cells: &'a mut Grid2d<[f32; 3]>,
+    emitter: &'b LightEmitter,
+    opa: &'c O,
+}
+
+impl<'a, 'b, 'c, O: OpacityMap> LightWrite<'a, 'b, 'c, O> {
+    /// Fraction of light passing through the cells between the emitter and the position
+    fn transmittance(&self, pos: Vec2i) -> f32 {
+        let mut t = 1.0;
+        for p in line::bresenham(self.emitter.pos, pos).skip(1) {
+            if p == pos {
+                break;
+            }
+            t *= 1.0 - self.opa.light_block(p);
+        }
+        t
+    }
+}
+
+impl<'a, 'b, 'c, O: OpacityMap> FovWrite for LightWrite<'a, 'b, 'c, O> {
+    fn on_refresh<T: OpacityMap>(&mut self, _params: &FovRefreshParams<T>) {}
+
+    fn light(&mut self, pos: Vec2i) {
+        let e = self.emitter;
+        let dist = (pos - e.pos).len_f32();
+        let x = e.intensity * e.falloff.attenuate(dist, e.radius as f32) * self.transmittance(pos);
+
+        if let Some(c) = self.cells.get_mut(pos) {
+            for i in 0..3 {
+                c[i] += e.color[i] * x;
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+/*!
+Remembered map: last observed states of cells and entities
+
+[`FowData`](crate::rl::shadow::FowData) only knows if a cell has been seen. [`MapMemory`] stores what
+was there (e.g. doors and items) and where entities were last seen, so that remembered-but-not-visible
+things can be drawn dimmed.
+*/
+
+use std::{collections::HashMap, hash::Hash};
+
+use crate::rl::{
+    grid2d::{Grid2d, Rect2i, Vec2i},
+    shadow::fov::FovData,
+};
+
+/// Last observed state of an entity
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remembered<E> {
+    /// Where it was last seen
+    pub pos: Vec2i,
+    pub state: E,
+}
+
+/// Last observed states of cells `C` and entities `E` keyed by `K`
+#[derive(Debug, Clone)]
+pub struct MapMemory<C, K: Eq + Hash, E> {
+    /// `None` if never seen
+    cells: Grid2d<Option<C>>,
+    entities: HashMap<K, Remembered<E>>,
+}
+
+/// Lifecycle
+impl<C: Clone, K: Eq + Hash, E> MapMemory<C, K, E> {
+    pub fn new(size: [usize; 2]) -> Self {
+        Self {
+            cells: Grid2d::new(size, None),
+            entities: HashMap::new(),
+        }
+    }
+
+    /// [w, h]
+    pub fn size(&self) -> [usize; 2] {
+        self.cells.size()
+    }
+
+    /// Forgets everything (e.g. on entering a new map)
+    pub fn clear(&mut self) {
+        self.cells.fill(None);
+        self.entities.clear();
+    }
+}
+
+/// Cells
+impl<C: Clone, K: Eq + Hash, E> MapMemory<C, K, E> {
+    /// Last observed state of the cell. `None` if it's never seen or outside of the map
+    pub fn cell(&self, pos: impl Into<Vec2i>) -> Option<&C> {
+        self.cells.get(pos).and_then(|c| c.as_ref())
+    }
+
+    pub fn remember_cell(&mut self, pos: impl Into<Vec2i>, state: C) {
+        let pos = pos.into();
+        if !self.cells.set(pos, Some(state)) {
+            log::warn!("tried to remember position out of the map: {:?}", pos);
+        }
+    }
+
+    pub fn forget_cell(&mut self, pos: impl Into<Vec2i>) {
+        self.cells.set(pos, None);
+    }
+
+    /// Remembers visible cells in the area
+    pub fn observe_cells(
+        &mut self,
+        area: Rect2i,
+        is_visible: impl Fn(Vec2i) -> bool,
+        mut state: impl FnMut(Vec2i) -> C,
+    ) {
+        let area = match area.intersect(&self.cells.bounds()) {
+            Some(a) => a,
+            None => return,
+        };
+
+        for pos in area.iter_pos() {
+            if is_visible(pos) {
+                self.cells[pos] = Some(state(pos));
+            }
+        }
+    }
+
+    /// Remembers cells in the field of view
+    pub fn observe_fov(&mut self, fov: &FovData, state: impl FnMut(Vec2i) -> C) {
+        let r = fov.radius() as i32;
+        let area = Rect2i::new(fov.origin() - Vec2i::new(r, r), [2 * r as u32 + 1; 2]);
+        self.observe_cells(area, |pos| fov.is_in_view(pos), state);
+    }
+}
+
+/// Entities
+impl<C: Clone, K: Eq + Hash, E> MapMemory<C, K, E> {
+    pub fn entity(&self, key: &K) -> Option<&Remembered<E>> {
+        self.entities.get(key)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = (&K, &Remembered<E>)> + '_ {
+        self.entities.iter()
+    }
+
+    /// Remembered entities at the position
+    pub fn entities_at(&self, pos: impl Into<Vec2i>) -> impl Iterator<Item = (&K, &E)> + '_ {
+        let pos = pos.into();
+        self.entities
+            .iter()
+            .filter(move |(_k, e)| e.pos == pos)
+            .map(|(k, e)| (k, &e.state))
+    }
+
+    pub fn remember_entity(&mut self, key: K, pos: impl Into<Vec2i>, state: E) {
+        let pos = pos.into();
+        self.entities.insert(key, Remembered { pos, state });
+    }
+
+    /// Forgets an entity (e.g. on death)
+    pub fn forget_entity(&mut self, key: &K) -> Option<Remembered<E>> {
+        self.entities.remove(key)
+    }
+
+    /// Updates entity memories with entities in view
+    ///
+    /// Remembered entities on visible cells are forgotten (we see they're not there anymore) and
+    /// then `visible` entities are remembered.
+    pub fn observe_entities(
+        &mut self,
+        is_visible: impl Fn(Vec2i) -> bool,
+        visible: impl IntoIterator<Item = (K, Vec2i, E)>,
+    ) {
+        self.entities.retain(|_k, e| !is_visible(e.pos));
+
+        for (key, pos, state) in visible {
+            self.remember_entity(key, pos, state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Remembered door state (`None` if it's not a door)
+    type Memory = MapMemory<Option<bool>, u32, &'static str>;
+
+    #[test]
+    fn test_observe_lit_cells() {
+        let mut memory = Memory::new([8, 8]);
+        let is_lit = |pos: Vec2i| pos.x < 4;
+
+        // the area is clipped with the map bounds
+        memory.observe_cells(Rect2i::new([-2, -2], [8, 8]), is_lit, |_pos| None);
+
+        assert_eq!(memory.cell([0, 0]), Some(&None));
+        assert_eq!(memory.cell([3, 5]), Some(&None));
+        // dark
+        assert_eq!(memory.cell([4, 0]), None);
+        // outside of the area
+        assert_eq!(memory.cell([0, 6]), None);
+        // outside of the map
+        assert_eq!(memory.cell([-1, 0]), None);
+
+        memory.forget_cell([0, 0]);
+        assert_eq!(memory.cell([0, 0]), None);
+    }
+
+    #[test]
+    fn test_door_snapshot() {
+        let mut memory = Memory::new([4, 4]);
+        let door = Vec2i::new(1, 1);
+        let area = Rect2i::new([0, 0], [4, 4]);
+        let state = |is_open| move |pos| if pos == door { Some(is_open) } else { None };
+
+        // seen while closed
+        memory.observe_cells(area.clone(), |_pos| true, state(false));
+        assert_eq!(memory.cell(door), Some(&Some(false)));
+        assert_eq!(memory.cell([0, 0]), Some(&None));
+
+        // opened out of sight: the memory is kept
+        memory.observe_cells(area.clone(), |pos| pos != door, state(true));
+        assert_eq!(memory.cell(door), Some(&Some(false)));
+
+        // seen again
+        memory.observe_cells(area, |_pos| true, state(true));
+        assert_eq!(memory.cell(door), Some(&Some(true)));
+    }
+
+    #[test]
+    fn test_entities() {
+        let mut memory = Memory::new([4, 4]);
+        memory.remember_entity(0, [1, 1], "slime");
+        memory.remember_entity(1, [2, 2], "bat");
+
+        // the slime is not at [1, 1] anymore and the bat moved out of sight
+        let is_visible = |pos: Vec2i| pos.x < 2;
+        memory.observe_entities(is_visible, vec![(2, Vec2i::new(0, 1), "player")]);
+
+        assert_eq!(memory.entity(&0), None);
+        assert_eq!(memory.entity(&1).map(|e| e.pos), Some(Vec2i::new(2, 2)));
+        assert_eq!(
+            memory.entities_at([0, 1]).collect::<Vec<_>>(),
+            vec![(&2, &"player")]
+        );
+
+        // dead actors are forgotten even if they're out of sight
+        let dead = memory.forget_entity(&1).unwrap();
+        assert_eq!(dead.state, "bat");
+        assert_eq!(memory.entity(&1), None);
+        assert_eq!(memory.entities().count(), 1);
+
+        memory.clear();
+        assert_eq!(memory.entities().count(), 0);
+    }
+}
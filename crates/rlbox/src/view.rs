@@ -4,6 +4,7 @@ Data for visualization
 
 pub mod actor;
 pub mod anim;
+pub mod autotile;
 pub mod camera;
 pub mod map;
 pub mod shadow;
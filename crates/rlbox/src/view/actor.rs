@@ -28,7 +28,10 @@ use snow2d::{
 use crate::{
     rl::grid2d::*,
     utils::DoubleSwap,
-    view::anim::{AnimPattern, LoopMode, MultiPatternAnimState},
+    view::{
+        anim::{AnimPattern, LoopMode, MultiPatternAnimState},
+        map::MapGeom,
+    },
 };
 
 /// Default actor image FPS
@@ -327,46 +330,47 @@ impl ActorImage {
     /// e.g., camera.
     ///
     /// Align the center of the sprite to the center of the cell.
-    pub fn pos_world_centered(&self, tiled: &tiled::Map) -> Vec2f {
-        let pos_prev = self.align_cell_center(self.state_diff.b().pos, tiled);
-        let pos_curr = self.align_cell_center(self.state_diff.a().pos, tiled);
+    pub fn pos_world_centered(&self, geom: &impl MapGeom) -> Vec2f {
+        let pos_prev = self.align_cell_center(self.state_diff.b().pos, geom);
+        let pos_curr = self.align_cell_center(self.state_diff.a().pos, geom);
         let mut pos = self.walk_dt.lerp(pos_prev, pos_curr);
         pos.floor_mut();
         pos
     }
 
     /// Align the bottom-center of an actor to the bottom-center of a cell
-    fn align_cell_center(&self, pos: Vec2i, tiled: &tiled::Map) -> Vec2f {
-        crate::render::tiled::t2w_center(pos, &tiled)
+    fn align_cell_center(&self, pos: Vec2i, geom: &impl MapGeom) -> Vec2f {
+        crate::render::tiled::t2w_center(pos, geom)
     }
 
     /// Base node position in world coordinates
-    pub fn base_pos_world(&self, tiled: &tiled::Map) -> Vec2f {
-        let pos_prev = Self::align_base(self.state_diff.b().pos, tiled);
-        let pos_curr = Self::align_base(self.state_diff.a().pos, tiled);
+    pub fn base_pos_world(&self, geom: &impl MapGeom) -> Vec2f {
+        let pos_prev = Self::align_base(self.state_diff.b().pos, geom);
+        let pos_curr = Self::align_base(self.state_diff.a().pos, geom);
         let mut pos = self.walk_dt.lerp(pos_prev, pos_curr);
         pos.floor_mut();
         pos
     }
 
-    /// Align the center of the sprite to the bottom-center of the cell
-    fn align_base(pos: Vec2i, tiled: &tiled::Map) -> Vec2f {
-        let delta = Vec2f::new(0.0, tiled.tile_height as f32 / 2.0);
-        crate::render::tiled::t2w_center(pos, &tiled) + delta
+    /// Base position of an actor standing at the cell: aligns the center of the sprite to the
+    /// bottom-center of the cell
+    pub fn align_base(pos: Vec2i, geom: &impl MapGeom) -> Vec2f {
+        let delta = Vec2f::new(0.0, geom.tile_size()[1] as f32 / 2.0);
+        crate::render::tiled::t2w_center(pos, geom) + delta
     }
 
     /// Image position in world coordinates
-    pub fn img_pos_world(&self, tiled: &tiled::Map) -> Vec2f {
-        let pos_prev = self.align_img(self.state_diff.b().pos, tiled);
-        let pos_curr = self.align_img(self.state_diff.a().pos, tiled);
+    pub fn img_pos_world(&self, geom: &impl MapGeom) -> Vec2f {
+        let pos_prev = self.align_img(self.state_diff.b().pos, geom);
+        let pos_curr = self.align_img(self.state_diff.a().pos, geom);
         let mut pos = self.walk_dt.lerp(pos_prev, pos_curr);
         pos.floor_mut();
         pos
     }
 
     /// Align the center of the sprite to the bottom-center of the cell
-    fn align_img(&self, pos: Vec2i, tiled: &tiled::Map) -> Vec2f {
-        Self::align_base(pos, &tiled) + self.img_offset()
+    fn align_img(&self, pos: Vec2i, geom: &impl MapGeom) -> Vec2f {
+        Self::align_base(pos, geom) + self.img_offset()
     }
 
     pub fn img_offset(&self) -> Vec2f {
@@ -0,0 +1,323 @@
+/*!
+Autotiling: logical cell grid to renderable tile layers
+
+Generated levels (or any grid of [`CellKind`]) are turned into [`GidLayer`]s with [`AutotileRules`].
+The output layers are rendered with [`crate::render::tiled::render_gid_layers`] using a
+[`GidTextureMap`](crate::view::map::GidTextureMap) that maps the GIDs to the tileset textures.
+
+Rules are plain data and can be loaded from a file (e.g. RON):
+
+```ron
+AutotileRules(
+    layers: [
+        (name: "0-floor", kinds: [Floor, Door], tiles: Single(gid: 1)),
+        (name: "1-wall", kinds: [Wall], tiles: Blob47(first_gid: 17)),
+    ],
+)
+```
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::rl::{
+    dun::{CellKind, GeneratedLevel},
+    grid2d::{Grid2d, Vec2i},
+};
+
+/// Reduced 8-neighbour masks of the blob tile set in ascending order
+///
+/// Bits are set for connected neighbours: N = 1, NE = 2, E = 4, SE = 8, S = 16, SW = 32, W = 64
+/// and NW = 128. Corner bits are only set if both of the adjacent edges are set. The `i`-th tile of
+/// a [`AutotileKind::Blob47`] tile set is used for the `i`-th mask.
+pub const BLOB_47_MASKS: [u8; 47] = [
+    0, 1, 4, 5, 7, 16, 17, 20, 21, 23, 28, 29, 31, 64, 65, 68, 69, 71, 80, 81, 84, 85, 87, 92, 93,
+    95, 112, 113, 116, 117, 119, 124, 125, 127, 193, 197, 199, 209, 213, 215, 221, 223, 241, 245,
+    247, 253, 255,
+];
+
+/// Neighbour offsets in the order of mask bits (clockwise from north)
+const NEIGHBOURS: [[i32; 2]; 8] = [
+    [0, -1],
+    [1, -1],
+    [1, 0],
+    [1, 1],
+    [0, 1],
+    [-1, 1],
+    [-1, 0],
+    [-1, -1],
+];
+
+/// Rules to make tile layers from a cell grid
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutotileRules {
+    /// Output layers from bottom to top
+    pub layers: Vec<AutotileLayerRule>,
+}
+
+/// Rule for one output layer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutotileLayerRule {
+    /// Name of the output layer. Starts with a number (e.g. `0-floor`) to be filtered by layer
+    /// ranges just like Tiled layers
+    pub name: String,
+    /// Cells that are tiled. They're considered as connected to each other
+    pub kinds: Vec<CellKind>,
+    pub tiles: AutotileKind,
+}
+
+/// Layout of tiles in a tileset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutotileKind {
+    /// The same tile for every cell
+    Single { gid: u32 },
+    /// Blob (Wang 47) tiles in the order of [`BLOB_47_MASKS`], starting from `first_gid`
+    Blob47 { first_gid: u32 },
+    /// RPG Maker A2-style autotile made of 2x3 tiles. Each cell is composed of four mini tiles of
+    /// half size, so the tileset has to be registered with half tile size
+    A2 {
+        /// GID of the left-up mini tile of the tileset
+        first_gid: u32,
+        /// Number of mini tiles in a row of the tileset
+        columns: u32,
+        /// Left-up position of the autotile in mini tiles
+        origin: [u32; 2],
+    },
+}
+
+/// Layer of Tiled-compatible GIDs (global tile ids)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GidLayer {
+    pub name: String,
+    /// Number of sub tiles in a cell in each direction (`2` for A2 autotiles, `1` otherwise)
+    pub div: u32,
+    /// `0` for no tile. Indexed in sub tiles
+    pub gids: Grid2d<u32>,
+}
+
+impl GidLayer {
+    /// Returns `0` if the position is outside of the layer
+    pub fn gid(&self, sub_pos: [u32; 2]) -> u32 {
+        let pos = [sub_pos[0] as i32, sub_pos[1] as i32];
+        self.gids.get(pos).cloned().unwrap_or(0)
+    }
+}
+
+impl AutotileRules {
+    /// Makes tile layers from cells
+    ///
+    /// Cells outside of the grid are considered as connected to every cell.
+    pub fn apply(&self, cells: &Grid2d<CellKind>) -> Vec<GidLayer> {
+        self.layers.iter().map(|rule| rule.apply(cells)).collect()
+    }
+
+    pub fn apply_level(&self, level: &GeneratedLevel) -> Vec<GidLayer> {
+        self.apply(&level.cells)
+    }
+}
+
+impl AutotileLayerRule {
+    pub fn apply(&self, cells: &Grid2d<CellKind>) -> GidLayer {
+        let div = match self.tiles {
+            AutotileKind::A2 { .. } => 2,
+            _ => 1,
+        };
+
+        let mut layer = GidLayer {
+            name: self.name.clone(),
+            div,
+            gids: Grid2d::new([cells.w() * div as usize, cells.h() * div as usize], 0),
+        };
+
+        for (pos, cell) in cells.iter_pos() {
+            if !self.kinds.contains(cell) {
+                continue;
+            }
+
+            let mask = self.mask(cells, pos);
+            match self.tiles {
+                AutotileKind::Single { gid } => {
+                    layer.gids[pos] = gid;
+                }
+                AutotileKind::Blob47 { first_gid } => {
+                    layer.gids[pos] = first_gid + self::blob_47_index(mask) as u32;
+                }
+                AutotileKind::A2 {
+                    first_gid,
+                    columns,
+                    origin,
+                } => {
+                    for (qx, qy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter().cloned() {
+                        let src = self::a2_mini_tile(mask, qx, qy);
+                        let src = [origin[0] + src[0], origin[1] + src[1]];
+                        let dst = [pos.x * 2 + qx as i32, pos.y * 2 + qy as i32];
+                        layer.gids[dst] = first_gid + src[0] + src[1] * columns;
+                    }
+                }
+            }
+        }
+
+        layer
+    }
+
+    /// 8-neighbour mask of connected cells
+    fn mask(&self, cells: &Grid2d<CellKind>, pos: Vec2i) -> u8 {
+        let mut mask = 0;
+        for (i, offset) in NEIGHBOURS.iter().enumerate() {
+            let nb = pos + Vec2i::from(offset);
+            if cells.get(nb).map_or(true, |c| self.kinds.contains(c)) {
+                mask |= 1 << i;
+            }
+        }
+        mask
+    }
+}
+
+/// Clears corner bits unless both of the adjacent edges are set
+pub fn reduce_blob_mask(mask: u8) -> u8 {
+    let mut res = mask;
+    // (corner, edge, edge)
+    for (c, e1, e2) in [(1, 0, 2), (3, 2, 4), (5, 4, 6), (7, 6, 0)].iter().cloned() {
+        if mask & (1 << e1) == 0 || mask & (1 << e2) == 0 {
+            res &= !(1 << c);
+        }
+    }
+    res
+}
+
+/// Index of a tile in a [`AutotileKind::Blob47`] tile set
+pub fn blob_47_index(mask: u8) -> usize {
+    let mask = self::reduce_blob_mask(mask);
+    BLOB_47_MASKS.binary_search(&mask).unwrap()
+}
+
+/// Position of a mini tile in an A2 autotile (4x6 mini tiles) for a quarter of a cell
+///
+/// The top-right tile of the autotile has inner corners and the lower 2x2 tiles have outer
+/// corners, edges and the center.
+fn a2_mini_tile(mask: u8, qx: u32, qy: u32) -> [u32; 2] {
+    // neighbours of the quarter: horizontal, vertical and diagonal
+    let (h, v, d) = match (qx, qy) {
+        (0, 0) => (6, 0, 7),
+        (1, 0) => (2, 0, 1),
+        (0, 1) => (6, 4, 5),
+        _ => (2, 4, 3),
+    };
+    let is = |bit: u32| mask & (1 << bit) != 0;
+
+    match (is(h), is(v)) {
+        (true, true) if is(d) => [2 - qx, 4 - qy],
+        (true, true) => [2 + qx, qy],
+        (false, true) => [3 * qx, 4 - qy],
+        (true, false) => [2 - qx, 2 + 3 * qy],
+        (false, false) => [3 * qx, 2 + 3 * qy],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const N: u8 = 1;
+    const NE: u8 = 2;
+    const E: u8 = 4;
+    const SE: u8 = 8;
+    const S: u8 = 16;
+    const SW: u8 = 32;
+    const W: u8 = 64;
+    const NW: u8 = 128;
+
+    fn rule(tiles: AutotileKind) -> AutotileLayerRule {
+        AutotileLayerRule {
+            name: "0-floor".to_string(),
+            kinds: vec![CellKind::Floor],
+            tiles,
+        }
+    }
+
+    #[test]
+    fn test_reduce_blob_mask() {
+        assert_eq!(self::reduce_blob_mask(0xFF), 0xFF);
+        assert_eq!(self::reduce_blob_mask(N | NE | E), N | NE | E);
+
+        // corner bits are dropped when an edge is missing
+        assert_eq!(self::reduce_blob_mask(N | NE), N);
+        assert_eq!(self::reduce_blob_mask(NE | SE | SW | NW), 0);
+        assert_eq!(self::reduce_blob_mask(!E), N | S | SW | W | NW);
+    }
+
+    #[test]
+    fn test_blob_47_index() {
+        // sorted for binary search, and already reduced
+        assert!(BLOB_47_MASKS.windows(2).all(|w| w[0] < w[1]));
+        for mask in BLOB_47_MASKS.iter() {
+            assert_eq!(self::reduce_blob_mask(*mask), *mask);
+        }
+
+        // every mask has a tile
+        for mask in 0..=255u8 {
+            let i = self::blob_47_index(mask);
+            assert_eq!(BLOB_47_MASKS[i], self::reduce_blob_mask(mask));
+        }
+
+        assert_eq!(self::blob_47_index(0), 0);
+        assert_eq!(self::blob_47_index(N | NE), 1);
+        assert_eq!(self::blob_47_index(0xFF), 46);
+    }
+
+    #[test]
+    fn test_mask() {
+        let mut cells = Grid2d::new([3, 3], CellKind::Floor);
+        let rule = self::rule(AutotileKind::Blob47 { first_gid: 0 });
+
+        // outside is connected
+        assert_eq!(rule.mask(&cells, Vec2i::new(0, 0)), 0xFF);
+        assert_eq!(rule.mask(&cells, Vec2i::new(1, 1)), 0xFF);
+
+        cells[[2, 0]] = CellKind::Wall;
+        assert_eq!(rule.mask(&cells, Vec2i::new(1, 1)), !NE);
+
+        let layer = rule.apply(&cells);
+        assert_eq!(layer.gid([1, 1]), self::blob_47_index(!NE) as u32);
+        assert_eq!(layer.gid([2, 0]), 0);
+        assert_eq!(layer.gid([3, 0]), 0);
+    }
+
+    #[test]
+    fn test_a2_mini_tile() {
+        let quarters = |mask| {
+            [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|(qx, qy)| self::a2_mini_tile(mask, *qx, *qy))
+                .collect::<Vec<_>>()
+        };
+
+        // center
+        assert_eq!(quarters(0xFF), vec![[2, 4], [1, 4], [2, 3], [1, 3]]);
+        // outer corners
+        assert_eq!(quarters(0), vec![[0, 2], [3, 2], [0, 5], [3, 5]]);
+        // inner corners
+        assert_eq!(
+            quarters(N | E | S | W),
+            vec![[2, 0], [3, 0], [2, 1], [3, 1]]
+        );
+        // vertical edges
+        assert_eq!(quarters(N | S), vec![[0, 4], [3, 4], [0, 3], [3, 3]]);
+        // horizontal edges
+        assert_eq!(quarters(E | W), vec![[2, 2], [1, 2], [2, 5], [1, 5]]);
+    }
+
+    #[test]
+    fn test_a2_layer() {
+        let rule = self::rule(AutotileKind::A2 {
+            first_gid: 1,
+            columns: 4,
+            origin: [0, 0],
+        });
+        let layer = rule.apply(&Grid2d::new([1, 1], CellKind::Floor));
+
+        assert_eq!(layer.div, 2);
+        assert_eq!(layer.gids.size(), [2, 2]);
+        // the center tiles: [2, 4], [1, 4], [2, 3], [1, 3]
+        assert_eq!(layer.gids.as_slice(), &[1 + 18, 1 + 17, 1 + 14, 1 + 13]);
+    }
+}
@@ -14,7 +14,158 @@ use {
     std::path::Path,
 };
 
-use crate::rl::rlmap::RlMap;
+use crate::{
+    rl::{
+        dun::GeneratedLevel,
+        grid2d::{Dir8, Vec2i},
+        rlmap::{self, DoorState, RlMap},
+    },
+    view::autotile::{AutotileRules, GidLayer},
+};
+
+/// Grid size and tile size of a map view
+pub trait MapGeom {
+    /// Number of cells in [x, y] directions
+    fn grid_size(&self) -> [u32; 2];
+    /// Size of one cell in pixels
+    fn tile_size(&self) -> [u32; 2];
+}
+
+impl MapGeom for tiled::Map {
+    /// Bounded by chunks if it's an infinite map
+    fn grid_size(&self) -> [u32; 2] {
+        let bounds = rlmap::tiled_grid_bounds(self);
+        [bounds.w(), bounds.h()]
+    }
+
+    fn tile_size(&self) -> [u32; 2] {
+        [self.tile_width, self.tile_height]
+    }
+}
+
+/// Roguelike map with a view that may or may not be backed by Tiled
+#[derive(Debug)]
+pub enum RlMapView {
+    Tiled(TiledRlMap),
+    Grid(GridRlMap),
+}
+
+impl RlMapView {
+    pub fn rlmap(&self) -> &RlMap {
+        match self {
+            Self::Tiled(map) => &map.rlmap,
+            Self::Grid(map) => &map.rlmap,
+        }
+    }
+
+    pub fn rlmap_mut(&mut self) -> &mut RlMap {
+        match self {
+            Self::Tiled(map) => &mut map.rlmap,
+            Self::Grid(map) => &mut map.rlmap,
+        }
+    }
+
+    /// Tiled view if any
+    pub fn tiled(&self) -> Option<&TiledRlMap> {
+        match self {
+            Self::Tiled(map) => Some(map),
+            Self::Grid(_) => None,
+        }
+    }
+
+    pub fn tiled_mut(&mut self) -> Option<&mut TiledRlMap> {
+        match self {
+            Self::Tiled(map) => Some(map),
+            Self::Grid(_) => None,
+        }
+    }
+
+    /// Sets the door state, swapping the tiles if it's backed by Tiled
+    pub fn set_door(&mut self, pos: Vec2i, state: DoorState) -> bool {
+        match self {
+            Self::Tiled(map) => map.set_door(pos, state),
+            Self::Grid(map) => map.rlmap.set_door(pos, state),
+        }
+    }
+}
+
+impl MapGeom for RlMapView {
+    fn grid_size(&self) -> [u32; 2] {
+        match self {
+            Self::Tiled(map) => map.tiled.grid_size(),
+            Self::Grid(map) => map.grid_size(),
+        }
+    }
+
+    fn tile_size(&self) -> [u32; 2] {
+        match self {
+            Self::Tiled(map) => map.tiled.tile_size(),
+            Self::Grid(map) => map.tile_size(),
+        }
+    }
+}
+
+impl From<TiledRlMap> for RlMapView {
+    fn from(map: TiledRlMap) -> Self {
+        Self::Tiled(map)
+    }
+}
+
+impl From<GridRlMap> for RlMapView {
+    fn from(map: GridRlMap) -> Self {
+        Self::Grid(map)
+    }
+}
+
+/// Roguelike map without Tiled map (e.g. procedurally generated map)
+#[derive(Debug)]
+pub struct GridRlMap {
+    pub rlmap: RlMap,
+    /// Size of one cell in pixels
+    pub tile_size: [u32; 2],
+    /// Tile layers (e.g. autotiled from the level), rendered with `idmap`
+    pub layers: Vec<GidLayer>,
+    pub idmap: GidTextureMap,
+}
+
+impl GridRlMap {
+    pub fn new(rlmap: RlMap, tile_size: [u32; 2]) -> Self {
+        Self {
+            rlmap,
+            tile_size,
+            layers: Vec::new(),
+            idmap: GidTextureMap::default(),
+        }
+    }
+
+    pub fn from_level(level: &GeneratedLevel, tile_size: [u32; 2]) -> Self {
+        Self::new(RlMap::from_level(level), tile_size)
+    }
+
+    /// Creates map with tile layers autotiled from the level
+    pub fn from_level_autotiled(
+        level: &GeneratedLevel,
+        tile_size: [u32; 2],
+        rules: &AutotileRules,
+        idmap: GidTextureMap,
+    ) -> Self {
+        let mut map = Self::from_level(level, tile_size);
+        map.layers = rules.apply_level(level);
+        map.idmap = idmap;
+        map
+    }
+}
+
+impl MapGeom for GridRlMap {
+    fn grid_size(&self) -> [u32; 2] {
+        let size = self.rlmap.size();
+        [size[0] as u32, size[1] as u32]
+    }
+
+    fn tile_size(&self) -> [u32; 2] {
+        self.tile_size
+    }
+}
 
 /// Bundle of Tiled map and internal roguelike map data
 #[derive(Debug)]
@@ -22,6 +173,8 @@ pub struct TiledRlMap {
     pub tiled: tiled::Map,
     pub rlmap: RlMap,
     pub idmap: GidTextureMap,
+    /// Actors placed in the `actors` object layer
+    pub actors: Vec<ActorPlacement>,
 }
 
 /// fs
@@ -32,20 +185,186 @@ impl TiledRlMap {
 
         let rlmap = RlMap::from_tiled(&tiled);
         let idmap = GidTextureMap::from_tiled(&resolved, &tiled, cache)?;
+        let actors = ActorPlacement::from_tiled(&tiled)?;
 
         Ok(Self {
             tiled,
             rlmap,
             idmap,
+            actors,
+        })
+    }
+}
+
+/// Actor placed in a Tiled object layer named `actors`
+///
+/// The object name is the actor type key. Properties:
+///
+/// * `dir` (string): `N`, `NE`, `E`, `SE`, `S` (default), `SW`, `W` or `NW`
+/// * `relation` (string): e.g. `hostile` or `friendly`
+/// * `script` (string): optional
+/// * `is-player` (bool): the actor controlled by the player if it's `true`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActorPlacement {
+    pub type_key: String,
+    /// Cell at the center of the object
+    pub pos: Vec2i,
+    pub dir: Dir8,
+    pub relation: Option<String>,
+    pub script: Option<String>,
+    pub is_player: bool,
+}
+
+impl ActorPlacement {
+    /// Reads the `actors` object layer. Empty if there's no such layer
+    pub fn from_tiled(tiled: &tiled::Map) -> Result<Vec<Self>> {
+        let group = match tiled.object_groups.iter().find(|g| g.name == "actors") {
+            Some(g) => g,
+            None => return Ok(Vec::new()),
+        };
+
+        let origin = rlmap::tiled_grid_bounds(tiled).left_up();
+        group
+            .objects
+            .iter()
+            .map(|obj| Self::from_tiled_object(tiled, obj, origin))
+            .collect()
+    }
+
+    fn from_tiled_object(tiled: &tiled::Map, obj: &tiled::Object, origin: Vec2i) -> Result<Self> {
+        ensure!(!obj.name.is_empty(), "actor object {} has no name", obj.id);
+
+        let string = |key: &str| match obj.properties.get(key) {
+            Some(tiled::PropertyValue::StringValue(s)) => Some(s.clone()),
+            _ => None,
+        };
+
+        let dir = match string("dir").as_deref() {
+            None => Dir8::S,
+            Some(s) => self::parse_dir8(s)
+                .with_context(|| format!("invalid dir `{}` of actor object {}", s, obj.id))?,
+        };
+
+        // tile objects are aligned to the left-down corner
+        let center = if obj.gid != 0 {
+            [obj.x + obj.width / 2.0, obj.y - obj.height / 2.0]
+        } else {
+            [obj.x + obj.width / 2.0, obj.y + obj.height / 2.0]
+        };
+        let pos = Vec2i::new(
+            (center[0] / tiled.tile_width as f32).floor() as i32,
+            (center[1] / tiled.tile_height as f32).floor() as i32,
+        ) - origin;
+
+        Ok(Self {
+            type_key: obj.name.clone(),
+            pos,
+            dir,
+            relation: string("relation"),
+            script: string("script"),
+            is_player: matches!(
+                obj.properties.get("is-player"),
+                Some(tiled::PropertyValue::BoolValue(true))
+            ),
         })
     }
 }
 
+fn parse_dir8(s: &str) -> Option<Dir8> {
+    Some(match s {
+        "N" => Dir8::N,
+        "NE" => Dir8::NE,
+        "E" => Dir8::E,
+        "SE" => Dir8::SE,
+        "S" => Dir8::S,
+        "SW" => Dir8::SW,
+        "W" => Dir8::W,
+        "NW" => Dir8::NW,
+        _ => return None,
+    })
+}
+
+/// Doors
+impl TiledRlMap {
+    /// Sets the door state and swaps the door tiles
+    ///
+    /// A door tile refers to the other variant with `door-open` or `door-closed` property (tile ID
+    /// in the same tileset).
+    pub fn set_door(&mut self, pos: Vec2i, state: DoorState) -> bool {
+        if !self.rlmap.set_door(pos, state) {
+            return false;
+        }
+
+        let key = if state.is_open() {
+            "door-open"
+        } else {
+            "door-closed"
+        };
+        let origin = rlmap::tiled_grid_bounds(&self.tiled).left_up();
+
+        for layer_ix in 0..self.tiled.layers.len() {
+            let gid =
+                match rlmap::tiled_layer_tile_mut(&mut self.tiled.layers[layer_ix], origin, pos) {
+                    Some(tile) => tile.gid,
+                    None => continue,
+                };
+
+            let new_gid = match self::door_variant(&self.tiled, gid, key) {
+                Some(gid) => gid,
+                None => continue,
+            };
+
+            if let Some(tile) =
+                rlmap::tiled_layer_tile_mut(&mut self.tiled.layers[layer_ix], origin, pos)
+            {
+                tile.gid = new_gid;
+            }
+        }
+
+        true
+    }
+
+    /// GID of a tile at the position as it looks with the door state (e.g. to draw remembered
+    /// doors). Returns `gid` as-is if it's not a door tile or the door is already in the state
+    pub fn door_gid(&self, pos: Vec2i, gid: u32, state: DoorState) -> u32 {
+        match self.rlmap.door(pos) {
+            Some(current) if current != state => {}
+            _ => return gid,
+        }
+
+        let key = if state.is_open() {
+            "door-open"
+        } else {
+            "door-closed"
+        };
+
+        self::door_variant(&self.tiled, gid, key).unwrap_or(gid)
+    }
+}
+
+/// GID of the door tile referred to by the property
+fn door_variant(tiled: &tiled::Map, gid: u32, key: &str) -> Option<u32> {
+    if gid == 0 {
+        return None;
+    }
+
+    let tileset = tiled.get_tileset_by_gid(gid)?;
+    let tile = tileset
+        .tiles
+        .iter()
+        .find(|t| t.id == gid - tileset.first_gid)?;
+
+    match tile.properties.get(key)? {
+        tiled::PropertyValue::IntValue(id) if *id >= 0 => Some(tileset.first_gid + *id as u32),
+        _ => None,
+    }
+}
+
 /// Maps Tiled's GID (global tile id) to a texture
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct GidTextureMap {
+    /// Sorted by `first_gid`
     spans: Vec<GidTextureSpan>,
-    tile_size: [u32; 2],
 }
 
 /// Span of Tiled's gid (global tile id) that uses one texture
@@ -53,6 +372,8 @@ pub struct GidTextureMap {
 struct GidTextureSpan {
     first_gid: u32,
     tex: Asset<Texture2dDrop>,
+    /// Size of one tile in the texture
+    tile_size: [u32; 2],
 }
 
 impl GidTextureMap {
@@ -78,10 +399,26 @@ impl GidTextureMap {
                     let img_path = tiled_dir_path.join(relative_img_path);
                     cache.load_sync(AssetKey::from_path(img_path)).unwrap()
                 },
+                tile_size,
             });
         }
 
-        Ok(Self { spans, tile_size })
+        Ok(Self { spans })
+    }
+
+    /// Adds a texture for GIDs starting from `first_gid` (e.g. tilesets for autotiling)
+    pub fn add_texture(&mut self, first_gid: u32, tex: Asset<Texture2dDrop>, tile_size: [u32; 2]) {
+        let span = GidTextureSpan {
+            first_gid,
+            tex,
+            tile_size,
+        };
+        let ix = self
+            .spans
+            .iter()
+            .position(|s| s.first_gid > first_gid)
+            .unwrap_or(self.spans.len());
+        self.spans.insert(ix, span);
     }
 
     pub fn gid_to_tile(&self, gid: u32) -> Option<SharedSubTexture2d> {
@@ -97,17 +434,18 @@ impl GidTextureMap {
             let id = gid - span.first_gid;
             let tex_size = span.tex.get().unwrap().sub_tex_size_unscaled();
 
-            let n_cols = tex_size[0] as u32 / self.tile_size[0];
+            let tile_size = span.tile_size;
+            let n_cols = tex_size[0] as u32 / tile_size[0];
             let src_grid_x = id % n_cols;
             let src_grid_y = id / n_cols;
 
             return Some(SharedSubTexture2d {
                 tex: span.tex.clone(),
                 uv_rect: [
-                    self.tile_size[0] as f32 * src_grid_x as f32 / tex_size[0],
-                    self.tile_size[1] as f32 * src_grid_y as f32 / tex_size[1],
-                    self.tile_size[0] as f32 / tex_size[0],
-                    self.tile_size[1] as f32 / tex_size[1],
+                    tile_size[0] as f32 * src_grid_x as f32 / tex_size[0],
+                    tile_size[1] as f32 * src_grid_y as f32 / tex_size[1],
+                    tile_size[0] as f32 / tex_size[0],
+                    tile_size[1] as f32 / tex_size[1],
                 ],
             });
         }
@@ -115,3 +453,112 @@ impl GidTextureMap {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tiled map with 32x32 tiles, a `meta` layer and an `actors` object layer
+    fn tmx(infinite: bool, objects: &str) -> tiled::Map {
+        let zeros = vec!["0"; 16 * 16].join(",");
+        let data = if infinite {
+            format!(
+                r#"<chunk x="-16" y="-16" width="16" height="16">{}</chunk>"#,
+                zeros
+            )
+        } else {
+            zeros
+        };
+
+        let src = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="16" height="16" tilewidth="32" tileheight="32" infinite="{}">
+ <tileset firstgid="1" name="actors" tilewidth="32" tileheight="32" tilecount="1" columns="1">
+  <image source="actors.png" width="32" height="32"/>
+ </tileset>
+ <layer id="1" name="meta" width="16" height="16">
+  <data encoding="csv">{}</data>
+ </layer>
+ <objectgroup id="2" name="actors">{}</objectgroup>
+</map>"#,
+            infinite as u8, data, objects
+        );
+
+        tiled::parse(src.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_tile_object_position() {
+        // tile objects are aligned to the left-down corner
+        let map = self::tmx(
+            false,
+            r#"<object id="1" gid="1" name="mokusei-san" x="64" y="96" width="32" height="32"/>
+<object id="2" name="mokusei-san" x="64" y="64" width="32" height="32"/>"#,
+        );
+
+        let actors = ActorPlacement::from_tiled(&map).unwrap();
+        assert_eq!(actors.len(), 2);
+        assert_eq!(actors[0].pos, Vec2i::new(2, 2));
+        assert_eq!(actors[1].pos, Vec2i::new(2, 2));
+        assert_eq!(actors[0].dir, Dir8::S);
+        assert!(!actors[0].is_player);
+    }
+
+    #[test]
+    fn test_infinite_map_origin() {
+        // the map starts from the chunk at [-16, -16]
+        let map = self::tmx(
+            true,
+            r#"<object id="1" gid="1" name="mokusei-san" x="-448" y="-416" width="32" height="32"/>"#,
+        );
+
+        let actors = ActorPlacement::from_tiled(&map).unwrap();
+        assert_eq!(actors[0].pos, Vec2i::new(2, 2));
+    }
+
+    #[test]
+    fn test_properties() {
+        let map = self::tmx(
+            false,
+            r#"<object id="1" name="mokusei-san" x="0" y="0" width="32" height="32">
+  <properties>
+   <property name="dir" value="NE"/>
+   <property name="relation" value="hostile"/>
+   <property name="is-player" type="bool" value="true"/>
+  </properties>
+ </object>"#,
+        );
+
+        let actor = &ActorPlacement::from_tiled(&map).unwrap()[0];
+        assert_eq!(actor.type_key, "mokusei-san");
+        assert_eq!(actor.dir, Dir8::NE);
+        assert_eq!(actor.relation.as_deref(), Some("hostile"));
+        assert_eq!(actor.script, None);
+        assert!(actor.is_player);
+
+        // `is-player = false`
+        let map = self::tmx(
+            false,
+            r#"<object id="1" name="mokusei-san" x="0" y="0" width="32" height="32">
+  <properties><property name="is-player" type="bool" value="false"/></properties>
+ </object>"#,
+        );
+        assert!(!ActorPlacement::from_tiled(&map).unwrap()[0].is_player);
+
+        // invalid direction
+        let map = self::tmx(
+            false,
+            r#"<object id="1" name="mokusei-san" x="0" y="0" width="32" height="32">
+  <properties><property name="dir" value="up"/></properties>
+ </object>"#,
+        );
+        assert!(ActorPlacement::from_tiled(&map).is_err());
+
+        // no type key
+        let map = self::tmx(
+            false,
+            r#"<object id="1" x="0" y="0" width="32" height="32"/>"#,
+        );
+        assert!(ActorPlacement::from_tiled(&map).is_err());
+    }
+}
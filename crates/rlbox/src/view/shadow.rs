@@ -40,12 +40,21 @@ pub struct Shadow {
     pub fov: Double<FovData>,
     /// Fog of war (shadow on map)
     pub fow: Double<FowData>,
+    /// Lit area (every cell is lit by default)
+    pub light: Double<LightMap>,
+    /// Light sources applied on calculation
+    pub emitters: Vec<LightEmitter>,
     /// Interpolation value
     pub dt: ez::EasedDt,
     pub is_dirty: bool,
+    /// Shadowcasting variant used on calculation
+    pub algo: FovAlgorithm,
+    /// FoV radius used on calculation
+    radius: u32,
 }
 
 impl Shadow {
+    /// `radius`: [radius, max_radius]. FoV buffers grow if the radius exceeds `max_radius` later
     pub fn new(radius: [u32; 2], map_size: [usize; 2], anim_secs: f32, ease: ez::Ease) -> Self {
         Self {
             fov: Double {
@@ -56,8 +65,29 @@ impl Shadow {
                 a: FowData::new(map_size),
                 b: FowData::new(map_size),
             },
+            light: Double {
+                a: LightMap::bright(map_size),
+                b: LightMap::bright(map_size),
+            },
+            emitters: Vec::new(),
             dt: ez::EasedDt::new(anim_secs, ease),
             is_dirty: false,
+            algo: FovAlgorithm::default(),
+            radius: radius[0],
+        }
+    }
+
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    /// Changes FoV radius from the next calculation (e.g. lanterns and magic lights)
+    ///
+    /// The new FoV is blended with the previous one with different radius.
+    pub fn set_radius(&mut self, radius: u32) {
+        if radius != self.radius {
+            self.radius = radius;
+            self.mark_dirty();
         }
     }
 
@@ -65,6 +95,18 @@ impl Shadow {
         self.is_dirty = true;
     }
 
+    /// Sets the light level of unlit cells (e.g. `[0.0; 3]` for dark dungeons)
+    pub fn set_ambient(&mut self, ambient: [f32; 3]) {
+        self.light.a.ambient = ambient;
+        self.light.b.ambient = ambient;
+        self.mark_dirty();
+    }
+
+    /// If the cell is in the current FoV and lit
+    pub fn is_visible(&self, pos: Vec2i) -> bool {
+        self.fov.a.is_in_view(pos) && self.light.a.is_lit(pos)
+    }
+
     pub fn calculate(&mut self, origin: Vec2i, map: &impl OpacityMap) {
         // FoV is always cleared so we just swap them
         self.fov.swap();
@@ -72,9 +114,22 @@ impl Shadow {
         // FoW is continued from the previous state, so we'll copy it
         self.fow.b = self.fow.a.clone();
 
+        self.light.swap();
+        self.light.a.calculate(&self.emitters, map, self.algo);
+
         self.dt.reset();
 
-        crate::rl::shadow::refresh_fov_fow(&mut self.fov.a, &mut self.fow.a, None, origin, map);
+        // NOTE: the swapped buffer has the radius of two calculations before
+        // NOTE: FoW is uncovered only on lit cells
+        crate::rl::shadow::refresh_fov_fow(
+            &mut self.fov.a,
+            &mut self.fow.a,
+            Some(&self.light.a),
+            Some(self.radius),
+            origin,
+            map,
+            self.algo,
+        );
     }
 
     /// Call it every frame to animate FoV